@@ -7,12 +7,15 @@ use dominator::{
     events::MouseDown,
     html, svg,
 };
-use futures_signals::signal::{Signal, SignalExt};
+use futures_signals::{
+    map_ref,
+    signal::{Signal, SignalExt},
+};
 
 use onitama_lib::{
-    check_move,
+    legal_moves_with,
     state::{Piece, PlayerTurn},
-    ClientMsg, PieceKind,
+    ClientMsg, Move, PieceKind,
 };
 use web_sys::WebSocket;
 
@@ -22,6 +25,8 @@ use crate::App;
 pub enum Overlay {
     Highlight,
     Dot,
+    // a move from the opening book
+    Book,
 }
 
 static OVERLAY_CLASS: LazyLock<String> = LazyLock::new(|| {
@@ -53,6 +58,7 @@ impl App {
         });
 
         let selected = self.selected.clone();
+        let pending = self.pending.clone();
         let game = self.game.clone();
         let info = self.info.clone();
         let socket_clone = socket.clone();
@@ -67,24 +73,41 @@ impl App {
                 let from = selected.get();
                 let mut g = game.lock_mut();
                 let square = g.state.board[pos];
-                if !g.my_turn {
+                if !g.my_turn || g.result.is_some() {
                     return ;
                 }
-                if from != Some(pos) && square.map(|x|x.0) == Some(PlayerTurn::ACTIVE) {
-                    selected.set(Some(pos));
-                } else if from.is_some() && check_move(&mut g.state, from.unwrap(), pos).is_some() {
-                    let card = check_move(&mut g.state, from.unwrap(), pos).unwrap();
-
+                let mv = if let Some(p) = pending.replace(None) {
+                    legal_moves_with(&g.state, &g.rules).find(|m| *m == Move { spirit: Some(pos), ..p })
+                } else {
+                    let legal = from.and_then(|from| {
+                        legal_moves_with(&g.state, &g.rules).find(|m| m.from == from && m.to == pos && !m.is_pass())
+                    });
+                    // the wind spirit can be moved too, and it can swap with your own pawns
+                    let selectable = square.is_some_and(|x| {
+                        x.0 == PlayerTurn::ACTIVE || x.1 == PieceKind::Spirit
+                    });
+                    if from != Some(pos) && selectable && legal.is_none() {
+                        selected.set(Some(pos));
+                        return ;
+                    }
                     selected.set(None);
+                    match legal {
+                        // first the piece, then pick where the wind spirit goes
+                        Some(m) if m.spirit.is_some() => {
+                            pending.set(Some(m));
+                            return ;
+                        }
+                        m => m,
+                    }
+                };
+                if let Some(m) = mv {
                     g.my_turn = false;
 
-                    let msg = ClientMsg { from: from.unwrap(), to: pos, card };
+                    let msg = ClientMsg { mv: m };
                     let info = info.get_cloned();
 
                     let buf = msg.format_litama(info.0, info.1, g.state.active_eq_red);
                     socket_clone.send_with_str(&buf).unwrap();
-                } else {
-                    selected.set(None);
                 }
             })
             .apply(|mut dom| {
@@ -102,7 +125,16 @@ impl App {
                         )
                     }
                 };
-                for overlay in [Overlay::Highlight, Overlay::Dot] {
+                dom = dom.child(
+                    piece_render(&Piece(PlayerTurn::ACTIVE, PieceKind::Spirit))
+                    .class(&*OVERLAY_CLASS)
+                    .visible_signal(
+                        self.game.signal_ref(move |g| {
+                            g.state.board[pos].is_some_and(|x| x.1 == PieceKind::Spirit)
+                        }).dedupe()
+                    ).into_dom()
+                );
+                for overlay in [Overlay::Highlight, Overlay::Dot, Overlay::Book] {
                     dom = dom.child(
                         overlay.render().class(&*OVERLAY_CLASS)
                         .visible_signal(
@@ -117,17 +149,37 @@ impl App {
 
     fn get_overlay(&self, pos: usize) -> impl Signal<Item = Option<Overlay>> {
         let game = self.game.clone();
-        self.selected.signal_ref(move |&from| {
-            let from = from?;
-            let mut game = game.lock_mut();
-            if from == pos {
-                Some(Overlay::Highlight)
-            } else if check_move(&mut game.state, from, pos).is_some() {
-                Some(Overlay::Dot)
-            } else {
-                None
+        map_ref! {
+            let from = self.selected.signal(),
+            let pending = self.pending.signal(),
+            let book = self.game.signal_ref(|g| g.book.clone()) => {
+                let game = game.lock_ref();
+                if let Some(p) = *pending {
+                    if p.to == pos {
+                        Some(Overlay::Highlight)
+                    } else if legal_moves_with(&game.state, &game.rules).any(|m| m == Move { spirit: Some(pos), ..p }) {
+                        Some(Overlay::Dot)
+                    } else {
+                        None
+                    }
+                } else if let Some(from) = *from {
+                    if from == pos {
+                        Some(Overlay::Highlight)
+                    } else if book.iter().any(|m| m.from == from && m.to == pos && !m.is_pass()) {
+                        Some(Overlay::Book)
+                    } else if legal_moves_with(&game.state, &game.rules).any(|m| m.from == from && m.to == pos) {
+                        Some(Overlay::Dot)
+                    } else {
+                        None
+                    }
+                } else if book.iter().any(|m| m.from == pos && !m.is_pass()) {
+                    // pieces with a book move are marked until one is selected
+                    Some(Overlay::Book)
+                } else {
+                    None
+                }
             }
-        })
+        }
     }
 }
 
@@ -137,6 +189,7 @@ fn piece_render(piece: &Piece) -> DomBuilder<HtmlElement> {
         Piece(PlayerTurn::ACTIVE, PieceKind::Pawn) => "wP.svg",
         Piece(PlayerTurn::WAITING, PieceKind::King) => "bB.svg",
         Piece(PlayerTurn::WAITING, PieceKind::Pawn) => "bP.svg",
+        Piece(_, PieceKind::Spirit) => "spirit.svg",
     };
 
     DomBuilder::new_html("img")
@@ -166,6 +219,13 @@ impl Overlay {
                 .attr("fill", "green")
                 .attr("fill-opacity", "0.5")
             })),
+            Overlay::Book => dom.attr("viewBox", "-1 -1 2 2").child(svg!("circle", {
+                .attr("r", "0.3")
+                .attr("fill", "none")
+                .attr("stroke", "#3b6fb6")
+                .attr("stroke-width", "0.1")
+                .attr("stroke-opacity", "0.8")
+            })),
         }
     }
 }
@@ -1,11 +1,54 @@
 use std::sync::LazyLock;
 
-use dominator::{class, html, Dom};
+use dominator::{class, events::MouseDown, html, Dom};
 use futures_signals::signal::{Mutable, SignalExt};
 
-use onitama_lib::{in_card, state::PlayerTurn};
+use onitama_lib::{in_card, legal_moves_with, state::PlayerTurn, ClientMsg, Move};
+use web_sys::WebSocket;
 
-use crate::ServerMsg;
+use crate::{App, ServerMsg};
+
+impl App {
+    // one of your own cards, clicking it passes the card when none of your pieces can move
+    pub fn render_own_card(&self, card: usize, socket: &WebSocket) -> Dom {
+        static PASS: LazyLock<String> = LazyLock::new(|| {
+            class! {
+                .style("outline", "3px solid #4b8c27")
+                .style("cursor", "pointer")
+            }
+        });
+
+        fn pass(g: &ServerMsg, card: usize) -> Option<Move> {
+            let card = g.state.cards[&PlayerTurn::ACTIVE][card];
+            if !g.my_turn || g.result.is_some() {
+                return None;
+            }
+            legal_moves_with(&g.state, &g.rules).find(|m| m.is_pass() && m.card == card)
+        }
+
+        let game = self.game.clone();
+        let info = self.info.clone();
+        let socket_clone = socket.clone();
+
+        html!("div", {
+            .style("display", "inline-block")
+            .class_signal(&*PASS, self.game.signal_ref(move |g| pass(g, card).is_some()).dedupe())
+            .event(move |_: MouseDown| {
+                let mut g = game.lock_mut();
+                if let Some(m) = pass(&g, card) {
+                    g.my_turn = false;
+
+                    let msg = ClientMsg { mv: m };
+                    let info = info.get_cloned();
+
+                    let buf = msg.format_litama(info.0, info.1, g.state.active_eq_red);
+                    socket_clone.send_with_str(&buf).unwrap();
+                }
+            })
+            .child(render_card(&self.game, card, false))
+        })
+    }
+}
 
 pub fn render_card(game: &Mutable<ServerMsg>, card: usize, rotated: bool) -> Dom {
     static CARD: LazyLock<String> = LazyLock::new(|| {
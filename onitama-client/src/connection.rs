@@ -3,7 +3,13 @@ use std::{cell::OnceCell, time::Duration};
 
 use crate::{App, ServerMsg};
 use dominator::Dom;
-use onitama_lib::{state::State, Color, LitamaMsg, StateMsg};
+use onitama_lib::{
+    cards,
+    notation::NamedMove,
+    record::parse_result,
+    state::{State, Translate},
+    Color, LitamaMsg, Move, StateMsg,
+};
 use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
 use web_sys::{window, MessageEvent};
 
@@ -20,6 +26,7 @@ pub fn game_dom(url: &str) -> Dom {
     let game_clone = app.game.clone();
     let timestamp_clone = app.timestamp.clone();
     let info_clone = app.info.clone();
+    let error_clone = app.connection_error.clone();
     let socket_clone = socket.clone();
     let onmessage = Closure::wrap(Box::new(move |e: MessageEvent| {
         let buf = e.data().as_string().unwrap();
@@ -37,7 +44,7 @@ pub fn game_dom(url: &str) -> Dom {
                 index,
             } => {
                 socket_clone
-                    .send_with_str(&format!("spectate {match_id}"))
+                    .send_with_str(&format!("spectate {match_id} {token}"))
                     .unwrap();
                 info_clone.set((match_id, token));
                 PLAYER_IDX.with(|x| x.set(index)).unwrap();
@@ -48,13 +55,42 @@ pub fn game_dom(url: &str) -> Dom {
                     return;
                 };
                 let my_color = extra.indices.find(PLAYER_IDX.with(|x| *x.get().unwrap()));
-                let mut state = State::from_state(extra);
+                let rules = extra.rules;
+                // the server decides when the game is over, the board that we get may have hidden pieces
+                let Ok(result) = parse_result(&extra.winner) else {
+                    return;
+                };
+                let book_moves = extra.book_moves.clone();
+                // the server sends the custom cards, the game can not be shown
+                // when we already know a card with the same name but other moves
+                for def in &extra.custom_cards {
+                    if cards::register_card(def).is_err() {
+                        error_clone.set(Some("Unknown card"));
+                        socket_clone.close().unwrap();
+                        return;
+                    }
+                }
+                let Ok(mut state) = State::from_state(extra) else {
+                    return;
+                };
                 let my_turn = state.active_eq_red == (my_color == Color::Red);
+                // the hints are for the player to move
+                let book: Vec<Move> = book_moves
+                    .iter()
+                    .filter(|_| my_turn)
+                    .filter_map(|m| m.parse::<NamedMove>().ok())
+                    .map(|m| m.translate(state.active_eq_red))
+                    .collect();
                 // pretend that we are the active player
                 state.active_eq_red = my_color == Color::Red;
+                let state: State = state.translate();
 
                 game_clone.set(ServerMsg {
-                    state: state.translate(),
+                    state,
+                    rules,
+                    book,
+                    result,
+                    my_color,
                     my_turn,
                     timers: [Duration::ZERO; 2],
                 });
@@ -67,9 +103,10 @@ pub fn game_dom(url: &str) -> Dom {
     socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
     onmessage.forget();
 
-    let done_clone = app.done.clone();
+    let error_clone = app.connection_error.clone();
     let onclose = Closure::wrap(Box::new(move |_| {
-        done_clone.set(true);
+        // keep the reason when we closed the connection ourselves
+        error_clone.lock_mut().get_or_insert("Disconnected");
     }) as Box<dyn FnMut(JsValue)>);
 
     socket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
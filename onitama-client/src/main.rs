@@ -7,8 +7,16 @@ use std::{
 };
 
 use dominator::{animation::timestamps, class, html, Dom};
-use futures_signals::signal::{Mutable, SignalExt};
-use onitama_lib::state::{PlayerTurn, State};
+use futures_signals::{
+    map_ref,
+    signal::{Mutable, Signal, SignalExt},
+};
+use onitama_lib::{
+    record::GameResult,
+    rules::RuleSet,
+    state::{PlayerTurn, State},
+    Color, Move,
+};
 use web_sys::WebSocket;
 
 use crate::{card::render_card, connection::game_dom};
@@ -17,15 +25,24 @@ use crate::{card::render_card, connection::game_dom};
 pub struct App {
     game: Mutable<ServerMsg>,
     selected: Mutable<Option<usize>>,
+    // a move that still needs a square for the wind spirit
+    pending: Mutable<Option<Move>>,
     timestamp: Mutable<f64>,
-    done: Mutable<bool>,
     info: Mutable<(String, String)>,
+    // set when the connection to the server is lost or the server sent cards that we can not use
+    connection_error: Mutable<Option<&'static str>>,
 }
 
 pub struct ServerMsg {
     pub state: State,
     pub my_turn: bool,
     pub timers: [Duration; 2],
+    // the result that the server sent, `None` while the game is in progress
+    pub result: Option<GameResult>,
+    pub my_color: Color,
+    pub rules: RuleSet,
+    // opening book moves that the server suggests
+    pub book: Vec<Move>,
 }
 
 pub fn main() {
@@ -58,11 +75,30 @@ impl App {
                 },
                 timers: [Duration::ZERO; 2],
                 my_turn: false,
+                result: None,
+                my_color: Color::Blue,
+                rules: RuleSet::DEFAULT,
+                book: Vec::new(),
             }),
             selected: Mutable::new(None),
+            pending: Mutable::new(None),
             timestamp: Mutable::new(0.),
-            done: Mutable::new(false),
             info: Mutable::new(("game_id".to_owned(), "token".to_owned())),
+            connection_error: Mutable::new(None),
+        }
+    }
+
+    // the text on top of the board, the result stays visible when the connection closes afterwards
+    fn status(&self) -> impl Signal<Item = Option<&'static str>> {
+        map_ref! {
+            let result = self.game.signal_ref(|g| match g.result {
+                Some(GameResult::Win(color)) if color == g.my_color => Some("You won"),
+                Some(GameResult::Win(_)) => Some("You lost"),
+                Some(GameResult::Draw) => Some("Draw"),
+                None => None,
+            }),
+            let error = self.connection_error.signal() =>
+            result.or(*error)
         }
     }
 
@@ -123,13 +159,8 @@ impl App {
                             .style("background", "white")
                             .style("border", "solid")
                         })
-                        .text_signal(self.game.signal_ref(move |g| {
-                            match g.my_turn {
-                                true => "You lost",
-                                false => "You won",
-                            }
-                        }))
-                        .visible_signal(self.done.signal().dedupe())
+                        .text_signal(self.status().map(|s| s.unwrap_or("")))
+                        .visible_signal(self.status().map(|s| s.is_some()).dedupe())
                     }))
                 }))
                 .children((0..5).map(|y|{
@@ -166,8 +197,8 @@ impl App {
                     .child(render_card(&self.game, 3, true))
                 }))
                 .child(html!("div", {
-                    .child(render_card(&self.game, 0, false))
-                    .child(render_card(&self.game, 1, false))
+                    .child(self.render_own_card(0, socket))
+                    .child(self.render_own_card(1, socket))
                 }))
                 .child(html!("div", {
                     .class(&*TEXT)
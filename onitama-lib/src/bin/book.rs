@@ -0,0 +1,164 @@
+use std::{
+    convert::{TryFrom, TryInto},
+    env,
+    error::Error,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+    process::exit,
+};
+
+use onitama_lib::{
+    bitboard::BitState,
+    book::Book,
+    card_to_pos,
+    engine::{evaluate, Engine, Limit, WIN},
+    record::GameRecord,
+    rules::RuleSet,
+    state::State,
+};
+
+// analysed moves that score this much worse than the best move are not added
+const MARGIN: i32 = 30;
+// the number of good moves that are followed in every position
+const WIDTH: usize = 2;
+
+// usage: book <book file> [--plies <n>] [--threads <n>] [--rules <rules>] [--analyse <depth> <card1,..,card5>] [files...]
+// the files are game records, or lines of `cards|variant|rules|history` like sqlite prints
+// `select cards, variant, rules, history from match`
+// an existing book file is extended
+// the analysis uses the official rules unless `--rules` comes first
+fn main() {
+    let mut args = env::args().skip(1);
+    let Some(output) = args.next() else {
+        eprintln!(
+            "usage: book <book file> [--plies <n>] [--threads <n>] [--rules <rules>] [--analyse <depth> <cards>] [files...]"
+        );
+        exit(2);
+    };
+    let mut book = match Path::new(&output).exists() {
+        true => {
+            let file = File::open(&output).expect("can not open book");
+            Book::read(&mut BufReader::new(file)).expect("can not read book")
+        }
+        false => Book::new(),
+    };
+
+    let mut plies = 12;
+    let mut threads = 1;
+    let mut rules = RuleSet::OFFICIAL;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--plies" => {
+                let n = args.next().and_then(|x| x.parse().ok());
+                plies = n.expect("expected the number of plies");
+            }
+            "--threads" => {
+                let n = args.next().and_then(|x| x.parse().ok());
+                threads = n.expect("expected the number of threads");
+            }
+            "--rules" => {
+                let r = args.next().and_then(|x| x.parse::<RuleSet>().ok());
+                rules = r.expect("expected the rules");
+                if !Book::applies_to(&rules) {
+                    eprintln!("the book can not be used with these rules");
+                    exit(2);
+                }
+            }
+            "--analyse" => {
+                let depth = args.next().and_then(|x| x.parse().ok());
+                let Some(depth) = depth.filter(|&d: &u32| d >= 1) else {
+                    eprintln!("expected a search depth of at least 1");
+                    exit(2);
+                };
+                let names = args.next().expect("expected five cards");
+                let cards: Vec<_> = names
+                    .split(',')
+                    .map(|c| card_to_pos(c.to_owned()).expect("unknown card name"))
+                    .collect();
+                let cards = cards.try_into().expect("expected five cards");
+                let mut engine = Engine::default();
+                engine.set_threads(threads);
+                engine.set_rules(rules);
+                let state = match BitState::try_from(&State::new_game(cards)) {
+                    Ok(state) => state,
+                    Err(err) => {
+                        eprintln!("{err}");
+                        exit(2);
+                    }
+                };
+                analyse(&mut book, &mut engine, &rules, state, depth, plies);
+            }
+            path => {
+                let text = fs::read_to_string(path).expect("can not read file");
+                match text.parse::<GameRecord>() {
+                    Ok(record) => add(book.add_record(&record, plies), path),
+                    Err(_) => {
+                        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                            let res = match line.split('|').map(str::trim).collect::<Vec<_>>()[..] {
+                                [cards, variant, rules, history] => {
+                                    book.add_history(cards, variant, rules, history, plies)
+                                }
+                                _ => Err("expected cards|variant|rules|history".into()),
+                            };
+                            add(res, line);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    eprintln!("{} positions", book.len());
+    let mut out = BufWriter::new(File::create(&output).expect("can not create book"));
+    book.write(&mut out).expect("failed to write book");
+    out.flush().expect("failed to write book");
+}
+
+fn add(res: Result<(), Box<dyn Error + Send + Sync>>, what: &str) {
+    if let Err(err) = res {
+        eprintln!("skipping {what}: {err}");
+    }
+}
+
+// score every move with the engine and follow the best ones, `engine` has to use `rules`
+fn analyse(
+    book: &mut Book,
+    engine: &mut Engine,
+    rules: &RuleSet,
+    state: BitState,
+    depth: u32,
+    plies: usize,
+) {
+    if plies == 0 || state.is_lost_with(rules) {
+        return;
+    }
+    let moves: Vec<_> = match rules.self_check {
+        true => state.moves().collect(),
+        false => state.legal_moves().collect(),
+    };
+    let mut scored: Vec<_> = moves
+        .into_iter()
+        .map(|m| {
+            let next = state.play(m);
+            let score = match next.is_lost_with(rules) {
+                true => WIN,
+                // the move itself is the first ply
+                false if depth == 1 => -evaluate(&next, rules),
+                false => -engine.search_bits(next, Limit::Depth(depth - 1)).score,
+            };
+            (m, score)
+        })
+        .collect();
+    scored.sort_by_key(|&(_, score)| -score);
+    let Some(&(_, best)) = scored.first() else {
+        return;
+    };
+    for (m, score) in scored.into_iter().take(WIDTH) {
+        if score < best - MARGIN {
+            break;
+        }
+        book.add_score(&state, m, score, depth as u8);
+        analyse(book, engine, rules, state.play(m), depth, plies - 1);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{convert::TryInto, env, process::exit, time::Instant};
+
+use onitama_lib::{
+    card_to_pos, cards,
+    perft::{check_reference, divide, perft, perft_slow, REFERENCE},
+    state::{NamedField, Perspective, State, Translate},
+};
+
+// usage:
+//   perft [max_depth]                                   check the reference suite
+//   perft <depth> <blue1> <blue2> <red1> <red2> <side>  divide for the starting position
+//   add --slow to compare against the `State` based move functions
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let slow = args.iter().any(|a| a == "--slow");
+    args.retain(|a| a != "--slow");
+
+    let depth: u32 = args
+        .first()
+        .map_or(5, |d| d.parse().expect("depth is a number"));
+
+    if args.len() <= 1 {
+        let start = Instant::now();
+        match check_reference(depth) {
+            Ok(()) => println!("{} card sets ok in {:?}", REFERENCE.len(), start.elapsed()),
+            Err((cards, depth, expected, found)) => {
+                println!("{cards:?} depth {depth}: expected {expected}, found {found}");
+                exit(1);
+            }
+        }
+        if slow {
+            for (cards, counts) in REFERENCE {
+                let state =
+                    State::new_game(cards.map(|c| card_to_pos(c.to_owned()).unwrap())).translate();
+                for (depth, &expected) in (1..=depth.min(4)).zip(counts.iter()) {
+                    assert_eq!(
+                        perft_slow(&state, depth),
+                        expected,
+                        "{cards:?} depth {depth}"
+                    );
+                }
+            }
+            println!("slow perft agrees");
+        }
+        return;
+    }
+
+    let cards: Vec<usize> = args[1..]
+        .iter()
+        .map(|c| card_to_pos(c.to_owned()).expect("unknown card name"))
+        .collect();
+    let state: State = State::new_game(cards.try_into().expect("expected 5 cards")).translate();
+    let moves = match divide(&state, depth) {
+        Ok(moves) => moves,
+        Err(err) => {
+            eprintln!("{err}");
+            exit(2);
+        }
+    };
+    let mut total = 0;
+    for (m, count) in moves {
+        let from: NamedField = Perspective::from_index(m.from).translate(state.active_eq_red);
+        let to: NamedField = Perspective::from_index(m.to).translate(state.active_eq_red);
+        println!("{}:{from}{to} {count}", cards::card(m.card).0);
+        total += count;
+    }
+    println!("total {total}");
+    if slow {
+        assert_eq!(perft_slow(&state, depth), total);
+        println!("slow perft agrees");
+    }
+    assert_eq!(perft(&state, depth).unwrap(), total);
+}
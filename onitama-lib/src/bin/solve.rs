@@ -0,0 +1,70 @@
+use std::{convert::TryFrom, env, fmt::Display, process::exit, time::Instant};
+
+use onitama_lib::{
+    bitboard::BitState,
+    notation::NamedMove,
+    pns::{Proof, Solver},
+    rules::RuleSet,
+    state::{NamedField, PlayerColor, State, Translate},
+};
+
+// usage: solve <fen> [max_plies] [rules]
+// proves a forced win or loss for the player to move and prints the line
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.len() > 3 {
+        eprintln!("usage: solve <fen> [max_plies] [rules]");
+        exit(2);
+    }
+    let state = match State::<NamedField, PlayerColor>::from_fen(&args[0]) {
+        Ok(state) => state,
+        Err(err) => fail("invalid fen", err),
+    };
+    let max_plies = match args.get(1).map(|x| x.parse::<u32>()) {
+        None => 15,
+        Some(Ok(plies)) => plies,
+        Some(Err(err)) => fail("invalid max_plies", err),
+    };
+    let rules = match args.get(2).map(|x| x.parse::<RuleSet>()) {
+        None => RuleSet::OFFICIAL,
+        Some(Ok(rules)) => rules,
+        Some(Err(err)) => fail("invalid rules", err),
+    };
+
+    let solver = Solver {
+        rules,
+        ..Solver::default()
+    };
+    let start = Instant::now();
+    let state = match BitState::try_from(&state) {
+        Ok(state) => state,
+        Err(err) => fail("unsupported position", err),
+    };
+    let solution = solver.solve(state, max_plies);
+    eprintln!("{} nodes in {:?}", solution.nodes, start.elapsed());
+
+    let side = ["blue", "red"][state.active_eq_red as usize];
+    match solution.proof {
+        Proof::Win(plies) => println!("{side} wins in {plies} plies"),
+        Proof::Loss(plies) => println!("{side} loses in {plies} plies"),
+        Proof::Unknown => println!("no proof within {max_plies} plies"),
+    }
+    let mut active_eq_red = state.active_eq_red;
+    let line: Vec<_> = solution
+        .line
+        .iter()
+        .map(|&m| {
+            let named: NamedMove = m.translate(active_eq_red);
+            active_eq_red ^= true;
+            named.to_string()
+        })
+        .collect();
+    if !line.is_empty() {
+        println!("{}", line.join(" "));
+    }
+}
+
+fn fail(what: &str, err: impl Display) -> ! {
+    eprintln!("{what}: {err}");
+    exit(2);
+}
@@ -0,0 +1,39 @@
+use std::{
+    env,
+    fs::File,
+    io::{BufWriter, Write},
+    time::Instant,
+};
+
+use onitama_lib::{card_to_pos, tablebase::Tablebase};
+
+// usage: tablebase <max_pawns> <card1> <card2> <card3> <card4> <card5> <output file>
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() != 7 {
+        eprintln!("usage: tablebase <max_pawns> <card1> <card2> <card3> <card4> <card5> <output>");
+        std::process::exit(2);
+    }
+    let max_pawns: u32 = args[0].parse().expect("max_pawns is a number");
+    let mut cards = [0; 5];
+    for (card, name) in cards.iter_mut().zip(&args[1..6]) {
+        *card = card_to_pos(name.to_owned()).expect("unknown card name");
+    }
+
+    let start = Instant::now();
+    let tb = match Tablebase::generate(cards, max_pawns) {
+        Ok(tb) => tb,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    println!("generated in {:?}", start.elapsed());
+
+    let (wins, losses, draws, longest) = tb.stats();
+    println!("wins {wins}, losses {losses}, draws {draws}, longest win {longest}");
+
+    let mut out = BufWriter::new(File::create(&args[6]).expect("can not create output file"));
+    tb.write(&mut out).expect("failed to write tablebase");
+    out.flush().expect("failed to write tablebase");
+}
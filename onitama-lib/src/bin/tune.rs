@@ -0,0 +1,201 @@
+use std::{
+    convert::{TryFrom, TryInto},
+    env, fs,
+    process::exit,
+};
+
+use onitama_lib::{
+    bitboard::BitState,
+    engine::{Engine, Limit},
+    eval::{features, Weights, FEATURES},
+    legal_moves_with,
+    record::{GameRecord, GameResult},
+    rules::RuleSet,
+    state::{NamedField, PlayerColor, PlayerTurn, State},
+    Color,
+};
+
+// positions with the result for the active player, 1 is a win and 0 a loss
+type Sample = ([i32; FEATURES], f64);
+
+// usage: tune [--self-play <games>] [--weights <file>] [record files...]
+// tunes the evaluation weights with texel's method and prints them as json
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut weights = Weights::default();
+    let mut self_play = 0;
+    let mut samples = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--self-play" => {
+                let games = args.next().and_then(|x| x.parse().ok());
+                self_play = games.expect("expected the number of games");
+            }
+            "--weights" => {
+                let json = fs::read_to_string(args.next().expect("expected a file"))
+                    .expect("can not read weights");
+                weights = serde_json::from_str(&json).expect("invalid weights");
+            }
+            path => {
+                let text = fs::read_to_string(path).expect("can not read record");
+                match text.parse() {
+                    Ok(record) => record_samples(&record, &mut samples),
+                    Err(err) => eprintln!("skipping {path}: {err}"),
+                }
+            }
+        }
+    }
+    let mut seed = 0x2545_f491_4f6c_dd1d;
+    for _ in 0..self_play {
+        self_play_samples(&weights, &mut seed, &mut samples);
+    }
+    if samples.is_empty() {
+        eprintln!("no positions, give record files or use --self-play");
+        exit(2);
+    }
+    eprintln!("{} positions", samples.len());
+
+    let k = fit_k(&samples, &weights);
+    eprintln!("k = {k:.3}, error = {:.6}", error(&samples, &weights, k));
+    let weights = tune(&samples, weights, k);
+    eprintln!("error = {:.6}", error(&samples, &weights, k));
+    println!("{}", serde_json::to_string_pretty(&weights).unwrap());
+}
+
+fn result_for(result: Option<GameResult>, state: &State) -> Option<f64> {
+    match result? {
+        GameResult::Win(color) => Some(((color == Color::Red) == state.active_eq_red) as u8 as f64),
+        GameResult::Draw => Some(0.5),
+    }
+}
+
+fn record_samples(record: &GameRecord, samples: &mut Vec<Sample>) {
+    let mut state: State = record.start().translate();
+    for m in &record.moves {
+        // unfinished games and the wind spirit are not used
+        let Some(result) = result_for(record.result, &state) else {
+            return;
+        };
+        let Ok(bits) = BitState::try_from(&state) else {
+            return;
+        };
+        samples.push((features(&bits, &record.rules), result));
+        match record.variant.make_move(state, m.mv, &record.rules) {
+            Ok(next) => state = next,
+            Err(_) => return,
+        }
+    }
+}
+
+fn self_play_samples(weights: &Weights, seed: &mut u64, samples: &mut Vec<Sample>) {
+    let mut random = |n: usize| {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        (*seed % n as u64) as usize
+    };
+    let mut cards: Vec<usize> = (0..16).collect();
+    for i in 0..5 {
+        let j = i + random(16 - i);
+        cards.swap(i, j);
+    }
+    let cards: [usize; 5] = cards[..5].try_into().unwrap();
+    let mut state: State = State::<NamedField, PlayerColor>::new_game(cards).translate();
+
+    // the engine plays with the official rules, so the whole game does
+    let rules = RuleSet::OFFICIAL;
+    let mut engine = Engine::default();
+    engine.set_weights(*weights);
+    let mut positions = Vec::new();
+    let mut result = None;
+    for ply in 0..200 {
+        if let Some(outcome) = state.outcome_with(&rules) {
+            let red_won = (outcome.winner == PlayerTurn::ACTIVE) == state.active_eq_red;
+            result = Some(GameResult::Win([Color::Blue, Color::Red][red_won as usize]));
+            break;
+        }
+        // the first moves are random so that the games are different
+        let m = match ply < 4 {
+            true => {
+                let moves: Vec<_> = legal_moves_with(&state, &rules).collect();
+                moves.get(random(moves.len().max(1))).copied()
+            }
+            false => {
+                engine
+                    .search(&state, Limit::Depth(3))
+                    .expect("the base cards do not move the wind spirit")
+                    .best
+            }
+        };
+        let Some(m) = m else {
+            let red_won = !state.active_eq_red;
+            result = Some(GameResult::Win([Color::Blue, Color::Red][red_won as usize]));
+            break;
+        };
+        positions.push(state.clone());
+        state.apply(m);
+    }
+    for state in positions {
+        if let Some(result) = result_for(result, &state) {
+            let bits =
+                BitState::try_from(&state).expect("the base cards do not move the wind spirit");
+            samples.push((features(&bits, &rules), result));
+        }
+    }
+}
+
+fn evaluate(weights: &[i32; FEATURES], features: &[i32; FEATURES]) -> f64 {
+    weights
+        .iter()
+        .zip(features)
+        .map(|(w, f)| w * f)
+        .sum::<i32>() as f64
+}
+
+fn error(samples: &[Sample], weights: &Weights, k: f64) -> f64 {
+    let weights = weights.to_array();
+    let total: f64 = samples
+        .iter()
+        .map(|(features, result)| {
+            let score = evaluate(&weights, features);
+            let expected = 1. / (1. + 10f64.powf(-k * score / 400.));
+            (result - expected).powi(2)
+        })
+        .sum();
+    total / samples.len() as f64
+}
+
+// the scaling between scores and winning chances that fits the current weights best
+fn fit_k(samples: &[Sample], weights: &Weights) -> f64 {
+    (1..=60)
+        .map(|i| i as f64 * 0.05)
+        .min_by(|&a, &b| error(samples, weights, a).total_cmp(&error(samples, weights, b)))
+        .unwrap()
+}
+
+// local search on every weight except the pawn, which sets the scale
+fn tune(samples: &[Sample], weights: Weights, k: f64) -> Weights {
+    let mut best = weights.to_array();
+    let mut best_error = error(samples, &weights, k);
+    let mut step = 16;
+    while step > 0 {
+        let mut improved = false;
+        for i in 1..FEATURES {
+            for delta in [step, -step] {
+                let mut next = best;
+                next[i] += delta;
+                let next_error = error(samples, &Weights::from_array(next), k);
+                if next_error < best_error {
+                    best = next;
+                    best_error = next_error;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if !improved {
+            step /= 2;
+        }
+    }
+    Weights::from_array(best)
+}
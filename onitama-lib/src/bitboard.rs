@@ -0,0 +1,468 @@
+use std::{array, collections::HashMap, convert::TryFrom, error::Error, marker::PhantomData};
+
+use crate::{
+    apply_offset, cards, get_offset, in_card,
+    rules::RuleSet,
+    state::{NamedField, Perspective, Piece, PlayerColor, PlayerTurn, PosRange, State, Translate},
+    Move, MoveError, PieceKind,
+};
+
+pub const ACTIVE: usize = 0;
+pub const WAITING: usize = 1;
+
+// the temple square of the active player, the waiting player wins by moving their king here
+pub const TEMPLE: usize = 22;
+// the temple square of the waiting player
+pub const OPP_TEMPLE: usize = 2;
+
+/// Compact version of [`State`] that is cheap to copy, compare and hash.
+///
+/// Every mask has one bit per square, indexed the same way as `State::board`,
+/// so the board is always seen from the perspective of the active player.
+/// Index [`ACTIVE`] of each array belongs to the active player, index [`WAITING`] to the other one.
+/// There is no room for the wind spirit, converting a state with the spirit or with cards
+/// that move it fails.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BitState {
+    pub pawns: [u32; 2],
+    pub kings: [u32; 2],
+    pub cards: [[u8; 2]; 2],
+    pub table_card: u8,
+    pub active_eq_red: bool,
+}
+
+// rotate the board 180 degrees, which is what happens when the active player changes
+pub fn rotate(mask: u32) -> u32 {
+    mask.reverse_bits() >> 7
+}
+
+// all squares that can be reached from `from` using `card`
+pub fn card_targets(card: usize, from: usize) -> u32 {
+    cards::card(card)
+        .1
+        .iter()
+        .filter_map(|&offset| apply_offset(offset, from))
+        .fold(0, |mask, pos| mask | 1 << pos)
+}
+
+impl BitState {
+    pub fn pieces(&self, side: usize) -> u32 {
+        self.pawns[side] | self.kings[side]
+    }
+
+    pub fn piece(&self, pos: usize) -> Option<Piece> {
+        let bit = 1 << pos;
+        [ACTIVE, WAITING].iter().find_map(|&side| {
+            let player = PlayerTurn {
+                is_active: side == ACTIVE,
+            };
+            if self.pawns[side] & bit != 0 {
+                Some(Piece(player, PieceKind::Pawn))
+            } else if self.kings[side] & bit != 0 {
+                Some(Piece(player, PieceKind::King))
+            } else {
+                None
+            }
+        })
+    }
+
+    // make the waiting player the active player
+    pub fn flip(&mut self) {
+        self.pawns = [rotate(self.pawns[WAITING]), rotate(self.pawns[ACTIVE])];
+        self.kings = [rotate(self.kings[WAITING]), rotate(self.kings[ACTIVE])];
+        self.cards.swap(ACTIVE, WAITING);
+        self.active_eq_red ^= true;
+    }
+
+    // check if any piece of the waiting player can move to `pos`
+    fn is_attacked(&self, pos: usize) -> bool {
+        let waiting = self.pieces(WAITING);
+        self.cards[WAITING]
+            .iter()
+            .any(|&c| card_targets(c as usize, pos) & waiting != 0)
+    }
+
+    pub fn is_check(&self) -> bool {
+        let king = self.kings[ACTIVE];
+        king != 0 && self.is_attacked(king.trailing_zeros() as usize)
+    }
+
+    // same as [`crate::check_move`], but returns the index of the card
+    pub fn check_move(&self, from: usize, to: usize) -> Option<usize> {
+        let own = self.pieces(ACTIVE);
+        if own & 1 << from == 0 || own & 1 << to != 0 || self.moved(from, to).is_check() {
+            return None;
+        }
+
+        let offset = get_offset(to, from)?;
+        self.cards[ACTIVE]
+            .iter()
+            .map(|&c| c as usize)
+            .find(|&c| in_card(offset, c))
+    }
+
+    // all moves that `make_move` accepts with the official rules, one per card that allows them
+    // these are the moves of the official rules, so when no piece can move they are passes with both cards
+    pub fn moves(&self) -> impl Iterator<Item = Move> {
+        self.piece_moves().chain(self.passes(&RuleSet::OFFICIAL))
+    }
+
+    pub(crate) fn piece_moves(&self) -> impl Iterator<Item = Move> {
+        let state = *self;
+        let own = state.pieces(ACTIVE);
+        (0..25)
+            .filter(move |from| own & 1 << from != 0)
+            .flat_map(move |from| {
+                (0..2).flat_map(move |i| {
+                    let card = state.cards[ACTIVE][i] as usize;
+                    let targets = card_targets(card, from) & !own;
+                    (0..25)
+                        .filter(move |to| targets & 1 << to != 0)
+                        .map(move |to| Move {
+                            card,
+                            from,
+                            to,
+                            spirit: None,
+                        })
+                })
+            })
+    }
+
+    // same as `moves`, but without the moves that walk into check
+    // passes are allowed when every piece move walks into check, but not when the king is in check already
+    pub fn legal_moves(&self) -> impl Iterator<Item = Move> {
+        self.legal_piece_moves()
+            .chain(self.passes(&RuleSet::DEFAULT))
+    }
+
+    fn legal_piece_moves(&self) -> impl Iterator<Item = Move> {
+        let state = *self;
+        self.piece_moves()
+            .filter(move |m| !state.moved(m.from, m.to).is_check())
+    }
+
+    // the active player has to pass when none of their pieces has a move that `rules` allow
+    pub fn can_pass_with(&self, rules: &RuleSet) -> bool {
+        match rules.self_check {
+            true => self.piece_moves().next().is_none(),
+            false => self.legal_piece_moves().next().is_none() && !self.is_check(),
+        }
+    }
+
+    fn passes(&self, rules: &RuleSet) -> impl Iterator<Item = Move> {
+        let cards = self.can_pass_with(rules).then_some(self.cards[ACTIVE]);
+        cards.into_iter().flatten().map(|c| Move::pass(c as usize))
+    }
+
+    // check if the active player has lost, with the temples of the official rules
+    pub fn is_lost(&self) -> bool {
+        self.kings[ACTIVE] == 0 || self.kings[WAITING] & 1 << TEMPLE != 0
+    }
+
+    // same as `is_lost`, but the temple squares come from `rules`
+    pub fn is_lost_with(&self, rules: &RuleSet) -> bool {
+        let temple = rules.temple().map_or(0, |t| 1 << t);
+        self.kings[ACTIVE] == 0 || self.kings[WAITING] & temple != 0
+    }
+
+    // apply a move without validating it
+    pub fn play(mut self, m: Move) -> Self {
+        let have = self.cards[ACTIVE]
+            .iter_mut()
+            .find(|x| **x as usize == m.card)
+            .unwrap();
+        *have = self.table_card;
+        self.table_card = m.card as u8;
+
+        let mut res = self.moved(m.from, m.to);
+        res.flip();
+        res
+    }
+
+    // move a piece without any checks and without switching the active player
+    fn moved(mut self, from: usize, to: usize) -> Self {
+        if from == to {
+            return self;
+        }
+        let (from_bit, to_bit) = (1 << from, 1 << to);
+        self.pawns[WAITING] &= !to_bit;
+        self.kings[WAITING] &= !to_bit;
+        if self.pawns[ACTIVE] & from_bit != 0 {
+            self.pawns[ACTIVE] ^= from_bit | to_bit;
+        } else {
+            self.kings[ACTIVE] ^= from_bit | to_bit;
+        }
+        self
+    }
+
+    // check for mate assuming that there is no check on the active player
+    pub fn is_mate(&self, rules: &RuleSet) -> bool {
+        let opp_king = self.kings[WAITING].trailing_zeros() as usize;
+        if let Some(offset) = rules.temple().and_then(|t| get_offset(opp_king, t)) {
+            if self.cards[WAITING]
+                .iter()
+                .any(|&c| in_card(offset, c as usize))
+            {
+                return true;
+            }
+        }
+        match rules.self_check {
+            true => self.moves().next().is_none(),
+            false => self.legal_moves().next().is_none(),
+        }
+    }
+
+    // moves into check and passes are checked with `rules`
+    pub fn make_move(
+        self,
+        card: usize,
+        from: usize,
+        to: usize,
+        rules: &RuleSet,
+    ) -> Result<Self, MoveError> {
+        if from == to {
+            return self.make_pass(card, rules);
+        }
+        if self.pieces(ACTIVE) & 1 << from == 0 {
+            return Err(MoveError::NotYourPiece);
+        }
+        if self.pieces(ACTIVE) & 1 << to != 0 {
+            return Err(MoveError::OntoOwnPiece);
+        }
+        if cards::get_card(card).is_none() {
+            return Err(MoveError::UnknownCard);
+        }
+        let offset = get_offset(to, from).ok_or(MoveError::TooFar)?;
+        if !in_card(offset, card) {
+            return Err(MoveError::InvalidForCard);
+        }
+        if !self.cards[ACTIVE].iter().any(|&x| x as usize == card) {
+            return Err(MoveError::CardNotInHand);
+        }
+        if !rules.self_check && self.moved(from, to).is_check() {
+            return Err(MoveError::SelfCheck);
+        }
+        Ok(self.play(Move {
+            card,
+            from,
+            to,
+            spirit: None,
+        }))
+    }
+
+    pub fn make_pass(self, card: usize, rules: &RuleSet) -> Result<Self, MoveError> {
+        if !self.can_pass_with(rules) {
+            return Err(MoveError::CanNotPass);
+        }
+        if !self.cards[ACTIVE].iter().any(|&x| x as usize == card) {
+            return Err(MoveError::CardNotInHand);
+        }
+        Ok(self.play(Move::pass(card)))
+    }
+}
+
+// the cards of the wind spirit can not be played without it
+fn check_cards(
+    cards: &[[usize; 2]],
+    table_card: usize,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut all = cards.iter().flatten().chain([&table_card]);
+    match all.any(|&c| !cards::spirit_moves(c).is_empty()) {
+        true => Err("BitState does not support the wind spirit".into()),
+        false => Ok(()),
+    }
+}
+
+impl TryFrom<&State> for BitState {
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn try_from(state: &State) -> Result<Self, Self::Error> {
+        check_cards(
+            &[
+                state.cards[&PlayerTurn::ACTIVE],
+                state.cards[&PlayerTurn::WAITING],
+            ],
+            state.table_card,
+        )?;
+        let mut res = BitState {
+            pawns: [0; 2],
+            kings: [0; 2],
+            cards: [
+                state.cards[&PlayerTurn::ACTIVE].map(|c| c as u8),
+                state.cards[&PlayerTurn::WAITING].map(|c| c as u8),
+            ],
+            table_card: state.table_card as u8,
+            active_eq_red: state.active_eq_red,
+        };
+        for (pos, piece) in state.board.iter().enumerate() {
+            if let Some(Piece(player, kind)) = piece {
+                let side = [WAITING, ACTIVE][player.is_active as usize];
+                match kind {
+                    PieceKind::Pawn => res.pawns[side] |= 1 << pos,
+                    PieceKind::King => res.kings[side] |= 1 << pos,
+                    PieceKind::Spirit => {
+                        return Err("BitState does not support the wind spirit".into())
+                    }
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl From<BitState> for State {
+    fn from(bits: BitState) -> Self {
+        State {
+            board: array::from_fn(|pos| bits.piece(pos)),
+            table_card: bits.table_card as usize,
+            cards: HashMap::from([
+                (PlayerTurn::ACTIVE, bits.cards[ACTIVE].map(|c| c as usize)),
+                (PlayerTurn::WAITING, bits.cards[WAITING].map(|c| c as usize)),
+            ]),
+            active_eq_red: bits.active_eq_red,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl TryFrom<&State<NamedField, PlayerColor>> for BitState {
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn try_from(state: &State<NamedField, PlayerColor>) -> Result<Self, Self::Error> {
+        check_cards(
+            &[
+                state.cards[&PlayerColor::BLUE],
+                state.cards[&PlayerColor::RED],
+            ],
+            state.table_card,
+        )?;
+        let active_eq_red = state.active_eq_red;
+        let active: PlayerColor = PlayerTurn::ACTIVE.translate(active_eq_red);
+        let waiting: PlayerColor = PlayerTurn::WAITING.translate(active_eq_red);
+
+        let mut res = BitState {
+            pawns: [0; 2],
+            kings: [0; 2],
+            cards: [
+                state.cards[&active].map(|c| c as u8),
+                state.cards[&waiting].map(|c| c as u8),
+            ],
+            table_card: state.table_card as u8,
+            active_eq_red,
+        };
+        for (field, piece) in NamedField::range().zip(&state.board) {
+            if let Some(Piece(player, kind)) = piece {
+                let field: Perspective = field.translate(active_eq_red);
+                let pos = field.index();
+                let side = [WAITING, ACTIVE][(*player == active) as usize];
+                match kind {
+                    PieceKind::Pawn => res.pawns[side] |= 1 << pos,
+                    PieceKind::King => res.kings[side] |= 1 << pos,
+                    PieceKind::Spirit => {
+                        return Err("BitState does not support the wind spirit".into())
+                    }
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl From<BitState> for State<NamedField, PlayerColor> {
+    fn from(bits: BitState) -> Self {
+        let state: State = bits.into();
+        state.translate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        can_pass_with, legal_moves_with,
+        state::{Outcome, Way},
+    };
+
+    // the active king can only move next to a waiting pawn
+    // ox and boar for the active player, horse and crab for the waiting one
+    fn cornered(in_check: bool) -> BitState {
+        let waiting = 1 << 14 | 1 << 21 | (in_check as u32) << 22;
+        BitState {
+            pawns: [0, waiting],
+            kings: [1 << 24, 1],
+            cards: [[0, 1], [2, 4]],
+            table_card: 3,
+            active_eq_red: false,
+        }
+    }
+
+    #[test]
+    fn pass_instead_of_self_check() {
+        let state = cornered(false);
+        assert!(!state.is_check());
+        assert!(state.moves().all(|m| !m.is_pass()));
+        let passes: Vec<_> = state.legal_moves().collect();
+        assert_eq!(passes, [Move::pass(0), Move::pass(1)]);
+        assert!(state.can_pass_with(&RuleSet::DEFAULT));
+        assert!(!state.can_pass_with(&RuleSet::OFFICIAL));
+        assert!(state.make_pass(0, &RuleSet::DEFAULT).is_ok());
+        assert_eq!(
+            state.make_pass(0, &RuleSet::OFFICIAL),
+            Err(MoveError::CanNotPass)
+        );
+        assert_eq!(
+            state.make_move(0, 24, 19, &RuleSet::DEFAULT),
+            Err(MoveError::SelfCheck)
+        );
+        assert!(state.make_move(0, 24, 19, &RuleSet::OFFICIAL).is_ok());
+
+        let game = State::from(state);
+        assert!(can_pass_with(&game, &RuleSet::DEFAULT));
+        assert!(!can_pass_with(&game, &RuleSet::OFFICIAL));
+        assert_eq!(
+            legal_moves_with(&game, &RuleSet::DEFAULT).collect::<Vec<_>>(),
+            passes
+        );
+        assert!(legal_moves_with(&game, &RuleSet::OFFICIAL).all(|m| !m.is_pass()));
+        assert!(game.clone().make_pass("ox", &RuleSet::DEFAULT).is_ok());
+        assert_eq!(
+            game.clone().make_pass("ox", &RuleSet::OFFICIAL).err(),
+            Some(MoveError::CanNotPass)
+        );
+        let from = Perspective::from_index(24);
+        let to = Perspective::from_index(19);
+        let res = game.clone().make_move("ox", from, to, &RuleSet::DEFAULT);
+        assert_eq!(res.err(), Some(MoveError::SelfCheck));
+        assert!(game.make_move("ox", from, to, &RuleSet::OFFICIAL).is_ok());
+    }
+
+    #[test]
+    fn no_pass_in_check() {
+        let state = cornered(true);
+        assert!(state.is_check());
+        assert_eq!(state.legal_moves().next(), None);
+        assert!(!state.can_pass_with(&RuleSet::DEFAULT));
+
+        let game = State::from(state);
+        assert!(!can_pass_with(&game, &RuleSet::DEFAULT));
+        assert!(game.make_pass("ox", &RuleSet::DEFAULT).is_err());
+    }
+
+    #[test]
+    fn mate_is_a_loss() {
+        let game: State = cornered(true).into();
+        let mate = Outcome {
+            winner: PlayerTurn::WAITING,
+            way: Way::Mate,
+        };
+        assert_eq!(game.outcome_with(&RuleSet::DEFAULT), Some(mate));
+        // with self check the king walks into check instead
+        assert_eq!(game.outcome_with(&RuleSet::OFFICIAL), None);
+        let other: State = cornered(false).into();
+        assert_eq!(other.outcome(), None);
+
+        let named: State<NamedField, PlayerColor> = game.translate();
+        let winner = named.outcome().unwrap().winner;
+        assert_eq!(winner, PlayerColor::RED);
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    error::Error,
+    io::{self, Read, Write},
+};
+
+use crate::{
+    bitboard::BitState,
+    cards,
+    notation::MoveList,
+    record::{GameRecord, GameResult, RecordMove},
+    rules::RuleSet,
+    state::{State, Translate},
+    symmetry::mirror_move,
+    variant::Variant,
+    Color, Move, Sides,
+};
+
+// the version was bumped when positions became the same up to symmetry
+const MAGIC: &[u8; 4] = b"OBK2";
+
+/// Opening book with the moves that were played or analysed in early positions.
+///
+/// Positions are looked up by [`BitState::canonical_key`], which includes all five cards,
+/// so every starting card set has its own lines. Mirrored positions and positions with
+/// the colors swapped share their moves, in the orientation of the canonical position.
+/// Cards are stored by index,
+/// so a book only works with the same custom cards that it was built with.
+#[derive(Default)]
+pub struct Book {
+    positions: HashMap<u64, Vec<BookMove>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BookMove {
+    // in the perspective of the player that makes it
+    pub mv: Move,
+    // how often the move was played in finished games, and how often the player that made it won
+    pub played: u32,
+    pub wins: u32,
+    // engine score for the player that makes the move, from the deepest analysis
+    pub score: Option<i32>,
+    pub depth: u8,
+}
+
+impl Book {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // number of positions in the book
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    fn entry(&mut self, state: &BitState, mv: Move) -> &mut BookMove {
+        let (canonical, mirrored) = state.canonical();
+        let mv = orient(mv, mirrored);
+        let moves = self.positions.entry(canonical.key()).or_default();
+        let i = match moves.iter().position(|x| x.mv == mv) {
+            Some(i) => i,
+            None => {
+                moves.push(BookMove {
+                    mv,
+                    played: 0,
+                    wins: 0,
+                    score: None,
+                    depth: 0,
+                });
+                moves.len() - 1
+            }
+        };
+        &mut moves[i]
+    }
+
+    // count a move from a finished game
+    pub fn add_played(&mut self, state: &BitState, mv: Move, won: bool) {
+        let entry = self.entry(state, mv);
+        entry.played += 1;
+        entry.wins += won as u32;
+    }
+
+    // scores from deeper searches replace the old ones
+    pub fn add_score(&mut self, state: &BitState, mv: Move, score: i32, depth: u8) {
+        let entry = self.entry(state, mv);
+        if depth >= entry.depth {
+            entry.score = Some(score);
+            entry.depth = depth.max(1);
+        }
+    }
+
+    // the lines in the book assume that the temples are in the middle, with other temples
+    // the same moves lead somewhere else, the other rules only change which moves are legal
+    pub fn applies_to(rules: &RuleSet) -> bool {
+        rules.temple == RuleSet::OFFICIAL.temple
+    }
+
+    // add the first `plies` moves of a game, unfinished games are skipped
+    // hidden pieces change how the game is played
+    pub fn add_record(
+        &mut self,
+        record: &GameRecord,
+        plies: usize,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if record.variant != Variant::Standard || !Self::applies_to(&record.rules) {
+            return Err("only standard games can be added".into());
+        }
+        // drawn games count as played but not won
+        let Some(result) = record.result else {
+            return Ok(());
+        };
+        let mut state: State = record.start().translate();
+        for m in record.moves.iter().take(plies) {
+            // the book has no room for the wind spirit
+            let Ok(bits) = BitState::try_from(&state) else {
+                break;
+            };
+            let mv: Move = m.mv.translate(state.active_eq_red);
+            let mover = [Color::Blue, Color::Red][state.active_eq_red as usize];
+            let won = result == GameResult::Win(mover);
+            self.add_played(&bits, mv, won);
+            let mv = m.mv;
+            state =
+                state.make_spirit_move(mv.card_name(), mv.from, mv.to, mv.spirit, &record.rules)?;
+        }
+        Ok(())
+    }
+
+    // add a match in the way the server stores it, like `ox,boar,horse,elephant,crab`, `standard`,
+    // `self_check=no;temple=c;first=side;ply_limit=none` and `boar:d1d2,..`
+    pub fn add_history(
+        &mut self,
+        cards: &str,
+        variant: &str,
+        rules: &str,
+        history: &str,
+        plies: usize,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let cards = cards
+            .split(',')
+            .map(|c| cards::find_card(c).ok_or("unknown card name"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let cards: [usize; 5] = cards.try_into().map_err(|_| "expected 5 starting cards")?;
+        let rules: RuleSet = rules.parse()?;
+        let MoveList(moves) = history.parse()?;
+
+        let mut record = GameRecord {
+            names: Sides {
+                blue: String::new(),
+                red: String::new(),
+            },
+            cards,
+            board: None,
+            started: None,
+            variant: variant.parse()?,
+            rules,
+            moves: moves
+                .into_iter()
+                .map(|mv| RecordMove { mv, time: None })
+                .collect(),
+            result: None,
+        };
+        record.result = record.final_result()?;
+        self.add_record(&record, plies)
+    }
+
+    // all known moves in this position
+    pub fn probe(&self, state: &BitState) -> Vec<BookMove> {
+        let (canonical, mirrored) = state.canonical();
+        let moves = self.positions.get(&canonical.key()).into_iter().flatten();
+        moves
+            .map(|m| BookMove {
+                mv: orient(m.mv, mirrored),
+                ..*m
+            })
+            .collect()
+    }
+
+    // same as `probe`, positions with the wind spirit are never in the book
+    pub fn probe_state(&self, state: &State) -> Vec<BookMove> {
+        match BitState::try_from(state) {
+            Ok(bits) => self.probe(&bits),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    // the analysed move with the best score, otherwise the move that was played most
+    pub fn best_move(&self, state: &BitState) -> Option<BookMove> {
+        let moves = self.probe(state);
+        let analysed = moves.iter().filter(|m| m.score.is_some());
+        match analysed.max_by_key(|m| (m.score, m.depth)) {
+            Some(m) => Some(*m),
+            None => moves.iter().max_by_key(|m| (m.played, m.wins)).copied(),
+        }
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&(self.positions.len() as u32).to_le_bytes())?;
+        for (key, moves) in &self.positions {
+            w.write_all(&key.to_le_bytes())?;
+            w.write_all(&[moves.len() as u8])?;
+            for m in moves {
+                w.write_all(&[m.mv.card as u8, m.mv.from as u8, m.mv.to as u8, m.depth])?;
+                w.write_all(&m.played.to_le_bytes())?;
+                w.write_all(&m.wins.to_le_bytes())?;
+                w.write_all(&m.score.unwrap_or(0).to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err("not an opening book".into());
+        }
+        let mut word = [0; 4];
+        r.read_exact(&mut word)?;
+        let len = u32::from_le_bytes(word);
+
+        let mut positions = HashMap::new();
+        for _ in 0..len {
+            let mut key = [0; 8];
+            r.read_exact(&mut key)?;
+            let mut count = [0];
+            r.read_exact(&mut count)?;
+            let mut moves = Vec::new();
+            for _ in 0..count[0] {
+                let mut head = [0; 4];
+                r.read_exact(&mut head)?;
+                let [card, from, to, depth] = head.map(|x| x as usize);
+                if card >= cards::card_count() || from >= 25 || to >= 25 {
+                    return Err("invalid move in opening book".into());
+                }
+                let mut numbers = [[0; 4]; 3];
+                for n in &mut numbers {
+                    r.read_exact(n)?;
+                }
+                moves.push(BookMove {
+                    mv: match from == to {
+                        true => Move::pass(card),
+                        false => Move {
+                            card,
+                            from,
+                            to,
+                            spirit: None,
+                        },
+                    },
+                    played: u32::from_le_bytes(numbers[0]),
+                    wins: u32::from_le_bytes(numbers[1]),
+                    // a depth of zero means that the move was never analysed
+                    score: (depth != 0).then(|| i32::from_le_bytes(numbers[2])),
+                    depth: depth as u8,
+                });
+            }
+            positions.insert(u64::from_le_bytes(key), moves);
+        }
+        Ok(Book { positions })
+    }
+}
+
+// moves are mirrored together with their position, which is only done when all cards have a mirror
+fn orient(mv: Move, mirrored: bool) -> Move {
+    match mirrored {
+        true => mirror_move(mv).expect("mirrored position without mirrored cards"),
+        false => mv,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{legal_moves, notation::NamedMove};
+
+    const CARDS: &str = "ox,boar,horse,elephant,crab";
+
+    // a finished game where both players capture whenever they can
+    fn history() -> String {
+        let cards = [0, 1, 2, 3, 4];
+        let mut state: State = State::new_game(cards).translate();
+        let mut moves = Vec::new();
+        while state.outcome().is_none() {
+            let all: Vec<_> = legal_moves(&state).collect();
+            let capture = all.iter().find(|m| state.board[m.to].is_some());
+            let m = *capture.unwrap_or(&all[moves.len() % all.len()]);
+            let named: NamedMove = m.translate(state.active_eq_red);
+            moves.push(named.to_string());
+            state.apply(m);
+        }
+        moves.join(",")
+    }
+
+    #[test]
+    fn add_history() {
+        let rules = RuleSet::DEFAULT.to_string();
+        let history = history();
+        let mut book = Book::new();
+        book.add_history(CARDS, "standard", &rules, &history, 4)
+            .unwrap();
+        assert_eq!(book.len(), 4);
+
+        let start = State::new_game([0, 1, 2, 3, 4]);
+        let first: NamedMove = history.split(',').next().unwrap().parse().unwrap();
+        let moves = book.probe(&BitState::try_from(&start).unwrap());
+        let played: Move = first.translate(start.active_eq_red);
+        assert_eq!(moves.iter().map(|m| m.mv).collect::<Vec<_>>(), [played]);
+
+        let mut other = Book::new();
+        let hidden = "light_and_shadow:red";
+        assert!(other
+            .add_history(CARDS, hidden, &rules, &history, 4)
+            .is_err());
+        assert!(other
+            .add_history(CARDS, "standard", "temple=none", &history, 4)
+            .is_err());
+        assert!(other
+            .add_history(CARDS, "standard", "chess", &history, 4)
+            .is_err());
+        let old = format!("{CARDS},{hidden}");
+        assert!(other
+            .add_history(&old, "standard", &rules, &history, 4)
+            .is_err());
+        assert!(other.is_empty());
+    }
+}
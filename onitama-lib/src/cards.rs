@@ -0,0 +1,207 @@
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{state::PlayerColor, Color, CARDS};
+
+// name, offsets into the 5x5 grid around the piece and stamp color
+pub type Card = (&'static str, &'static [usize], PlayerColor);
+
+// `BitState` stores card indices as u8
+const MAX_CARDS: usize = 256;
+
+// a card added at runtime, `CUSTOM` borrows from these
+struct Custom {
+    name: String,
+    offsets: Vec<usize>,
+    // offsets for the wind spirit, empty for cards that do not move it
+    spirit: Vec<usize>,
+}
+
+// cards added at runtime come after `CARDS`, they are never removed so indices stay valid
+// registering a card again reuses its slot, so there are at most `MAX_CARDS` cards in total
+static CUSTOM_DEFS: [OnceLock<Custom>; MAX_CARDS] = [const { OnceLock::new() }; MAX_CARDS];
+static CUSTOM: [OnceLock<Card>; MAX_CARDS] = [const { OnceLock::new() }; MAX_CARDS];
+static CUSTOM_LEN: AtomicUsize = AtomicUsize::new(0);
+static REGISTER: Mutex<()> = Mutex::new(());
+
+// panics for indices that are not from `find_card` or `register_card`, see `get_card`
+pub fn card(index: usize) -> &'static Card {
+    get_card(index).expect("unknown card index")
+}
+
+// `None` for indices that do not belong to a card
+pub fn get_card(index: usize) -> Option<&'static Card> {
+    match index.checked_sub(CARDS.len()) {
+        Some(custom) => CUSTOM.get(custom)?.get(),
+        None => CARDS.get(index),
+    }
+}
+
+// empty for cards that do not move the wind spirit as an extra move, only custom cards can
+pub fn spirit_moves(index: usize) -> &'static [usize] {
+    match index.checked_sub(CARDS.len()) {
+        Some(custom) => {
+            let custom = CUSTOM_DEFS.get(custom).and_then(OnceLock::get);
+            &custom.expect("unknown card index").spirit
+        }
+        None => &[],
+    }
+}
+
+pub fn card_count() -> usize {
+    CARDS.len() + CUSTOM_LEN.load(Ordering::Acquire)
+}
+
+pub fn find_card(name: &str) -> Option<usize> {
+    (0..card_count()).find(|&i| card(i).0 == name)
+}
+
+/// A card as written in a card file.
+///
+/// `moves` are (right, forward) steps for the player that holds the card,
+/// so the ox looks like this:
+///
+/// ```text
+/// {"name": "ox", "moves": [[0, 1], [1, 0], [0, -1]], "color": "blue"}
+/// ```
+///
+/// Way of the Wind cards also have `spirit_moves`, the steps that the wind spirit
+/// makes after one of your pieces moved. These cards are not built in, they have to be
+/// copied from the printed cards into a card file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardDef {
+    pub name: String,
+    pub moves: Vec<(i32, i32)>,
+    #[serde(default)]
+    pub spirit_moves: Vec<(i32, i32)>,
+    pub color: Color,
+}
+
+impl CardDef {
+    fn offsets(moves: &[(i32, i32)]) -> Result<Vec<usize>, Box<dyn Error + Send + Sync>> {
+        let mut offsets = moves
+            .iter()
+            .map(
+                |&(x, y)| match x.abs() <= 2 && y.abs() <= 2 && (x, y) != (0, 0) {
+                    true => Ok(((2 - y) * 5 + 2 + x) as usize),
+                    false => Err("card moves must be at most two squares away"),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        offsets.sort();
+        offsets.dedup();
+        Ok(offsets)
+    }
+}
+
+// add a card to the registry, registering the same card twice returns the same index
+pub fn register_card(def: &CardDef) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    // the name is used in the litama protocol and in move notation
+    if def.name.is_empty()
+        || def
+            .name
+            .contains(|c: char| c.is_whitespace() || c == ',' || c == ':')
+    {
+        return Err("card names can not be empty or contain spaces, ',' or ':'".into());
+    }
+    let offsets = CardDef::offsets(&def.moves)?;
+    let spirit = CardDef::offsets(&def.spirit_moves)?;
+    let color = PlayerColor {
+        is_red: def.color == Color::Red,
+    };
+
+    let _lock = REGISTER.lock().unwrap();
+    if let Some(index) = find_card(&def.name) {
+        let existing = card(index);
+        if existing.1 != &offsets[..] || existing.2 != color || spirit_moves(index) != &spirit[..] {
+            return Err("a different card with this name already exists".into());
+        }
+        return Ok(index);
+    }
+
+    let len = CUSTOM_LEN.load(Ordering::Acquire);
+    if CARDS.len() + len >= MAX_CARDS {
+        return Err("too many cards".into());
+    }
+    let res = CUSTOM_DEFS[len].set(Custom {
+        name: def.name.clone(),
+        offsets,
+        spirit,
+    });
+    assert!(res.is_ok(), "card slot is already used");
+    let custom = CUSTOM_DEFS[len].get().unwrap();
+    let res = CUSTOM[len].set((&custom.name, &custom.offsets, color));
+    assert!(res.is_ok(), "card slot is already used");
+    CUSTOM_LEN.store(len + 1, Ordering::Release);
+    Ok(CARDS.len() + len)
+}
+
+// the definition that `register_card` would turn into this card
+pub fn card_def(index: usize) -> CardDef {
+    let (name, offsets, color) = *card(index);
+    let moves = |offsets: &[usize]| {
+        offsets
+            .iter()
+            .map(|&o| (o as i32 % 5 - 2, 2 - o as i32 / 5))
+            .collect()
+    };
+    CardDef {
+        name: name.to_owned(),
+        moves: moves(offsets),
+        spirit_moves: moves(spirit_moves(index)),
+        color: [Color::Blue, Color::Red][color.is_red as usize],
+    }
+}
+
+// register every card in a JSON list of `CardDef`, returns their indices
+pub fn load_cards(json: &str) -> Result<Vec<usize>, Box<dyn Error + Send + Sync>> {
+    let defs: Vec<CardDef> = serde_json::from_str(json)?;
+    defs.iter().map(register_card).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_defs() {
+        for index in 0..CARDS.len() {
+            let def = card_def(index);
+            assert_eq!(register_card(&def).unwrap(), index);
+        }
+        let ox = card_def(find_card("ox").unwrap());
+        assert_eq!(ox.moves, [(0, 1), (1, 0), (0, -1)]);
+    }
+
+    #[test]
+    fn register() {
+        let json = r#"[
+            {"name": "test_hawk", "moves": [[-2, 2], [2, 2]], "color": "red"},
+            {"name": "test_breeze", "moves": [[0, 1]], "spirit_moves": [[1, 1]], "color": "blue"}
+        ]"#;
+        let indices = load_cards(json).unwrap();
+        assert_eq!(load_cards(json).unwrap(), indices);
+        for &index in &indices {
+            assert!(index >= CARDS.len());
+            assert_eq!(register_card(&card_def(index)).unwrap(), index);
+        }
+        assert_eq!(spirit_moves(indices[1]), [8]);
+        assert!(get_card(MAX_CARDS).is_none());
+
+        let mut other = card_def(indices[0]);
+        other.moves.push((0, 1));
+        assert!(register_card(&other).is_err());
+        other.name = "test hawk".to_owned();
+        assert!(register_card(&other).is_err());
+        other.name = "test_far".to_owned();
+        other.moves = vec![(3, 0)];
+        assert!(register_card(&other).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! Draws, which depend on the whole game instead of only the current position.
+
+use std::collections::HashMap;
+
+use crate::{
+    rules::RuleSet,
+    state::{NamedField, PlayerColor, State},
+};
+
+// a position that occurs this often is a draw
+const REPETITIONS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Draw {
+    // the same position with the same player to move occurred three times
+    Repetition,
+    // the ply limit of the rules was reached
+    PlyLimit,
+}
+
+/// Keeps track of the positions of a game to find draws.
+///
+/// Call [`DrawTracker::push`] after every move, the game is over as soon as it returns a draw.
+/// A position that is won is never a draw, check [`State::outcome_with`] first.
+#[derive(Debug, Clone)]
+pub struct DrawTracker {
+    seen: HashMap<String, u32>,
+    plies: u32,
+    rules: RuleSet,
+    draw: Option<Draw>,
+}
+
+// the order of the cards in a hand does not matter
+fn key(state: &State<NamedField, PlayerColor>) -> String {
+    let mut state = state.clone();
+    for hand in state.cards.values_mut() {
+        hand.sort();
+    }
+    state.to_fen()
+}
+
+impl DrawTracker {
+    pub fn new(start: &State<NamedField, PlayerColor>, rules: &RuleSet) -> Self {
+        DrawTracker {
+            seen: HashMap::from([(key(start), 1)]),
+            plies: 0,
+            rules: *rules,
+            draw: None,
+        }
+    }
+
+    // add the position after a move, the first draw is kept
+    pub fn push(&mut self, state: &State<NamedField, PlayerColor>) -> Option<Draw> {
+        self.plies += 1;
+        let seen = self.seen.entry(key(state)).or_default();
+        *seen += 1;
+        if self.draw.is_none() {
+            if *seen >= REPETITIONS {
+                self.draw = Some(Draw::Repetition);
+            } else if self
+                .rules
+                .ply_limit
+                .is_some_and(|limit| self.plies >= limit)
+            {
+                self.draw = Some(Draw::PlyLimit);
+            }
+        }
+        self.draw
+    }
+
+    pub fn draw(&self) -> Option<Draw> {
+        self.draw
+    }
+
+    pub fn plies(&self) -> u32 {
+        self.plies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repetition() {
+        let start = State::new_game([0, 1, 2, 3, 4]);
+        let mut draws = DrawTracker::new(&start, &RuleSet::DEFAULT);
+        let mut other = start.clone();
+        other.cards.values_mut().for_each(|hand| hand.reverse());
+        // the order of the cards in a hand does not matter
+        assert_eq!(draws.push(&other), None);
+        assert_eq!(draws.push(&start), Some(Draw::Repetition));
+        assert_eq!(draws.plies(), 2);
+    }
+
+    #[test]
+    fn ply_limit() {
+        let start = State::new_game([0, 1, 2, 3, 4]);
+        let rules: RuleSet = "ply_limit=2".parse().unwrap();
+        let mut draws = DrawTracker::new(&start, &rules);
+        let mut next = start.clone();
+        next.active_eq_red ^= true;
+        assert_eq!(draws.push(&next), None);
+        assert_eq!(draws.push(&start), Some(Draw::PlyLimit));
+        // the first draw is kept
+        assert_eq!(draws.push(&start), Some(Draw::PlyLimit));
+        assert_eq!(draws.draw(), Some(Draw::PlyLimit));
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    convert::TryFrom,
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bitboard::{BitState, ACTIVE, WAITING},
+    book::{Book, BookMove},
+    eval::Weights,
+    rules::RuleSet,
+    state::State,
+    tablebase::{Tablebase, Value},
+    zobrist::{Bound, Entry, TranspositionTable},
+    Move,
+};
+
+// score of a won position, wins that take more moves score slightly lower
+pub const WIN: i32 = 1_000_000;
+pub const MAX_DEPTH: u32 = 64;
+
+const DEFAULT_TT_MB: usize = 16;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Limit {
+    Depth(u32),
+    Time(Duration),
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best: Option<Move>,
+    // every move is in the perspective of the player that makes it
+    pub pv: Vec<Move>,
+    // score for the active player
+    pub score: i32,
+    // a depth of 0 means that the move came from the opening book
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+pub fn is_win_score(score: i32) -> bool {
+    score.abs() > WIN / 2
+}
+
+// win scores are stored relative to the position in the table, so that they can be reused at any ply
+fn to_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        s if is_win_score(s) && s > 0 => s + ply as i32,
+        s if is_win_score(s) => s - ply as i32,
+        s => s,
+    }
+}
+
+fn from_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        s if is_win_score(s) && s > 0 => s - ply as i32,
+        s if is_win_score(s) => s + ply as i32,
+        s => s,
+    }
+}
+
+// fails for positions with the wind spirit
+pub fn search(state: &State, limit: Limit) -> Result<SearchResult, Box<dyn Error + Send + Sync>> {
+    Engine::default().search(state, limit)
+}
+
+pub struct Engine {
+    nodes: u64,
+    deadline: Option<Instant>,
+    stopped: bool,
+    // set when the main thread is done, the helper threads stop searching
+    stop: Arc<AtomicBool>,
+    // principal variation of the previous iteration, searched first
+    pv: Vec<Move>,
+    // shared by all threads
+    tt: Arc<TranspositionTable>,
+    threads: usize,
+    tablebase: Option<Arc<Tablebase>>,
+    book: Option<Arc<Book>>,
+    rules: RuleSet,
+    weights: Weights,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new(DEFAULT_TT_MB)
+    }
+}
+
+impl Engine {
+    pub fn new(tt_mb: usize) -> Self {
+        Engine {
+            nodes: 0,
+            deadline: None,
+            stopped: false,
+            stop: Arc::new(AtomicBool::new(false)),
+            pv: Vec::new(),
+            tt: Arc::new(TranspositionTable::new(tt_mb)),
+            threads: 1,
+            tablebase: None,
+            book: None,
+            rules: RuleSet::OFFICIAL,
+            weights: Weights::default(),
+        }
+    }
+
+    // positions in the tablebase are not searched any further
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.tablebase = tablebase;
+    }
+
+    // positions in the book are played right away
+    pub fn set_book(&mut self, book: Option<Arc<Book>>) {
+        self.book = book;
+    }
+
+    // the official rules are used by default
+    pub fn set_rules(&mut self, rules: RuleSet) {
+        self.rules = rules;
+        self.tt.clear();
+    }
+
+    pub fn set_weights(&mut self, weights: Weights) {
+        self.weights = weights;
+        self.tt.clear();
+    }
+
+    // lazy smp, every thread searches the same position and they share the transposition table
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn clear(&mut self) {
+        self.tt.clear();
+    }
+
+    pub fn search(
+        &mut self,
+        state: &State,
+        limit: Limit,
+    ) -> Result<SearchResult, Box<dyn Error + Send + Sync>> {
+        Ok(self.search_bits(BitState::try_from(state)?, limit))
+    }
+
+    pub fn search_bits(&mut self, state: BitState, limit: Limit) -> SearchResult {
+        let (max_depth, deadline) = match limit {
+            Limit::Depth(depth) => (depth.clamp(1, MAX_DEPTH), None),
+            Limit::Time(time) => (MAX_DEPTH, Some(Instant::now() + time)),
+        };
+        self.nodes = 0;
+        self.stopped = false;
+        self.pv.clear();
+
+        if let Some(m) = self.book_move(&state) {
+            return SearchResult {
+                best: Some(m.mv),
+                pv: vec![m.mv],
+                score: m.score.unwrap_or(0),
+                depth: 0,
+                nodes: 0,
+            };
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+        thread::scope(|scope| {
+            let helpers: Vec<_> = (1..self.threads)
+                .map(|i| {
+                    let mut helper = self.helper();
+                    // half of the helpers search one iteration ahead, so that the threads do not all do the same work
+                    let first = (1 + i as u32 % 2).min(max_depth);
+                    scope.spawn(move || helper.iterate(state, first, max_depth, None).nodes)
+                })
+                .collect();
+            let mut res = self.iterate(state, 1, max_depth, deadline);
+            self.stop.store(true, Ordering::Relaxed);
+            res.nodes += helpers.into_iter().map(|h| h.join().unwrap()).sum::<u64>();
+            res
+        })
+    }
+
+    // an engine for another thread, with the same settings and transposition table
+    fn helper(&self) -> Engine {
+        Engine {
+            nodes: 0,
+            deadline: None,
+            stopped: false,
+            stop: self.stop.clone(),
+            pv: Vec::new(),
+            tt: self.tt.clone(),
+            threads: 1,
+            tablebase: self.tablebase.clone(),
+            book: None,
+            rules: self.rules,
+            weights: self.weights,
+        }
+    }
+
+    // iterative deepening, the helper threads start at a later depth
+    fn iterate(
+        &mut self,
+        state: BitState,
+        first: u32,
+        max_depth: u32,
+        deadline: Option<Instant>,
+    ) -> SearchResult {
+        let mut res = SearchResult {
+            best: None,
+            pv: Vec::new(),
+            score: 0,
+            depth: 0,
+            nodes: 0,
+        };
+        for depth in first..=max_depth {
+            // always finish the first iteration so that there is a move to play
+            self.deadline = deadline.filter(|_| depth > 1);
+
+            let mut pv = Vec::new();
+            let score = self.negamax(state, state.key(), depth, 0, -WIN, WIN, true, &mut pv);
+            if self.stopped {
+                break;
+            }
+
+            self.pv = pv.clone();
+            res = SearchResult {
+                best: pv.first().copied(),
+                pv,
+                score,
+                depth,
+                nodes: self.nodes,
+            };
+            if is_win_score(score) {
+                break;
+            }
+        }
+        res.nodes = self.nodes;
+        res
+    }
+
+    // book moves are only used when they are legal with the current rules
+    fn book_move(&self, state: &BitState) -> Option<BookMove> {
+        let book = self.book.as_ref()?;
+        if !Book::applies_to(&self.rules) {
+            return None;
+        }
+        let m = book.best_move(state)?;
+        let legal = match self.rules.self_check {
+            true => state.moves().any(|x| x == m.mv),
+            false => state.legal_moves().any(|x| x == m.mv),
+        };
+        legal.then_some(m)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        state: BitState,
+        key: u64,
+        depth: u32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        on_pv: bool,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        self.nodes += 1;
+        // most nodes are leaves, so the time is checked before the leaves return
+        if self.nodes.is_multiple_of(1024)
+            && (self.deadline.is_some_and(|d| Instant::now() >= d)
+                || self.stop.load(Ordering::Relaxed))
+        {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+        if state.is_lost_with(&self.rules) {
+            return -WIN + ply as i32;
+        }
+        // tablebases are generated with the official rules
+        let tb_value = self.tablebase.as_ref().filter(|_| {
+            ply > 0 && self.rules.self_check && self.rules.temple == RuleSet::OFFICIAL.temple
+        });
+        if let Some(value) = tb_value.and_then(|tb| tb.probe(&state)) {
+            return match value {
+                Value::Win(d) => WIN - ply as i32 - d as i32,
+                Value::Loss(d) => -WIN + ply as i32 + d as i32,
+                Value::Draw => 0,
+            };
+        }
+        if depth == 0 {
+            return self.weights.evaluate(&state, &self.rules);
+        }
+
+        let entry = self.tt.get(key);
+        if let Some(e) = entry.filter(|e| ply > 0 && e.depth as u32 >= depth) {
+            let score = from_tt(e.score, ply);
+            match e.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {}
+            }
+        }
+
+        let pv_move = self.pv.get(ply).copied().filter(|_| on_pv);
+        let tt_move = entry.and_then(|e| e.best);
+        let mut moves: Vec<_> = match self.rules.self_check {
+            true => state.moves().collect(),
+            false => state.legal_moves().collect(),
+        };
+        if moves.is_empty() {
+            return -WIN + ply as i32;
+        }
+        moves.sort_by_key(|m| match Some(*m) {
+            x if x == pv_move => i32::MIN,
+            x if x == tt_move => i32::MIN + 1,
+            _ => -order(&state, m, &self.rules),
+        });
+
+        let alpha_orig = alpha;
+        let mut best = None;
+        let mut child_pv = Vec::new();
+        for m in moves {
+            child_pv.clear();
+            let on_pv = Some(m) == pv_move;
+            let score = -self.negamax(
+                state.play(m),
+                state.play_key(key, m),
+                depth - 1,
+                ply + 1,
+                -beta,
+                -alpha,
+                on_pv,
+                &mut child_pv,
+            );
+            if self.stopped {
+                return 0;
+            }
+            if score > alpha {
+                alpha = score;
+                best = Some(m);
+                pv.clear();
+                pv.push(m);
+                pv.append(&mut child_pv);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        let bound = match alpha {
+            a if a >= beta => Bound::Lower,
+            a if a > alpha_orig => Bound::Exact,
+            _ => Bound::Upper,
+        };
+        self.tt.insert(
+            key,
+            Entry {
+                depth: depth as u8,
+                score: to_tt(alpha, ply),
+                bound,
+                best,
+            },
+        );
+        alpha
+    }
+}
+
+// evaluation with the default weights, from the perspective of the active player
+pub fn evaluate(state: &BitState, rules: &RuleSet) -> i32 {
+    Weights::default().evaluate(state, rules)
+}
+
+// moves that win or capture are searched first
+fn order(state: &BitState, m: &Move, rules: &RuleSet) -> i32 {
+    let to = 1 << m.to;
+    let is_king = state.kings[ACTIVE] & 1 << m.from != 0;
+    if state.kings[WAITING] & to != 0 || (is_king && rules.opp_temple() == Some(m.to)) {
+        2
+    } else if state.pawns[WAITING] & to != 0 {
+        1
+    } else {
+        0
+    }
+}
@@ -0,0 +1,139 @@
+//! Static evaluation for the engine, with weights that can be tuned.
+//!
+//! Every feature is counted for both players from their own perspective and
+//! the result is the difference, so the score is for the active player.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bitboard::{card_targets, BitState, ACTIVE},
+    rules::RuleSet,
+};
+
+pub const FEATURES: usize = 4;
+
+/// Weights of the evaluation features, in centipawn-like units.
+///
+/// The default only counts pawns, like the engine always did.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Weights {
+    pub pawn: i32,
+    // for every step that the king is closer to the temple of the opponent
+    pub temple_distance: i32,
+    // for every move that the cards in hand allow
+    pub mobility: i32,
+    // for every piece that can move onto the temple of the opponent
+    pub temple_control: i32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            pawn: 100,
+            temple_distance: 0,
+            mobility: 0,
+            temple_control: 0,
+        }
+    }
+}
+
+impl Weights {
+    pub fn evaluate(&self, state: &BitState, rules: &RuleSet) -> i32 {
+        let features = features(state, rules);
+        self.to_array()
+            .iter()
+            .zip(features)
+            .map(|(w, f)| w * f)
+            .sum()
+    }
+
+    // in the same order as `features`
+    pub fn to_array(&self) -> [i32; FEATURES] {
+        [
+            self.pawn,
+            self.temple_distance,
+            self.mobility,
+            self.temple_control,
+        ]
+    }
+
+    pub fn from_array(values: [i32; FEATURES]) -> Self {
+        let [pawn, temple_distance, mobility, temple_control] = values;
+        Weights {
+            pawn,
+            temple_distance,
+            mobility,
+            temple_control,
+        }
+    }
+}
+
+// the value of every feature for the active player minus the value for the waiting player
+// the temple features are zero when `rules` have no temples
+pub fn features(state: &BitState, rules: &RuleSet) -> [i32; FEATURES] {
+    let mut flipped = *state;
+    flipped.flip();
+    let opp_temple = rules.opp_temple();
+    let (active, waiting) = (
+        side_features(state, opp_temple),
+        side_features(&flipped, opp_temple),
+    );
+    std::array::from_fn(|i| active[i] - waiting[i])
+}
+
+fn side_features(state: &BitState, opp_temple: Option<usize>) -> [i32; FEATURES] {
+    let own = state.pieces(ACTIVE);
+    let pawns = state.pawns[ACTIVE].count_ones() as i32;
+
+    // steps for a king that can move in every direction, kings that are gone count as far away
+    let king = state.kings[ACTIVE].trailing_zeros() as usize;
+    let closeness = match (king, opp_temple) {
+        (32, _) | (_, None) => 0,
+        (k, Some(t)) => 4 - (k % 5).abs_diff(t % 5).max(k / 5) as i32,
+    };
+
+    let mut mobility = 0;
+    let mut temple_control = 0;
+    for from in (0..25).filter(|from| own & 1 << from != 0) {
+        let targets = state.cards[ACTIVE]
+            .iter()
+            .fold(0, |acc, &c| acc | card_targets(c as usize, from));
+        mobility += state.cards[ACTIVE]
+            .iter()
+            .map(|&c| (card_targets(c as usize, from) & !own).count_ones() as i32)
+            .sum::<i32>();
+        temple_control += opp_temple.is_some_and(|t| targets & 1 << t != 0) as i32;
+    }
+    [pawns, closeness, mobility, temple_control]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temples_from_rules() {
+        // the active king is in the middle, the waiting king is still on its temple
+        let state = BitState {
+            pawns: [0, 0],
+            kings: [1 << 12, 1 << 2],
+            cards: [[0, 1], [2, 4]],
+            table_card: 3,
+            active_eq_red: false,
+        };
+        let official = features(&state, &RuleSet::OFFICIAL);
+        assert_eq!(official[1], 2);
+
+        let no_temples: RuleSet = "temple=none".parse().unwrap();
+        let features = features(&state, &no_temples);
+        assert_eq!(features, [0, 0, official[2], 0]);
+
+        let weights = Weights {
+            temple_distance: 10,
+            ..Weights::default()
+        };
+        assert_eq!(weights.evaluate(&state, &RuleSet::OFFICIAL), 20);
+        assert_eq!(weights.evaluate(&state, &no_temples), 0);
+    }
+}
@@ -1,4 +1,25 @@
+pub mod bitboard;
+pub mod book;
+pub mod cards;
+pub mod draw;
+#[cfg(feature = "engine")]
+pub mod engine;
+#[cfg(feature = "engine")]
+pub mod eval;
+#[cfg(feature = "engine")]
+pub mod mcts;
+pub mod notation;
+pub mod perft;
+#[cfg(feature = "engine")]
+pub mod pns;
+pub mod record;
+pub mod rules;
 pub mod state;
+pub mod symmetry;
+pub mod tablebase;
+pub mod variant;
+pub mod wind;
+pub mod zobrist;
 
 extern crate serde;
 
@@ -7,36 +28,33 @@ use serde::{Deserialize, Serialize};
 use std::{
     cmp::{max, min},
     collections::HashMap,
-    convert::TryInto,
+    convert::{TryFrom, TryInto},
+    error::Error,
+    fmt,
     iter::FromIterator,
     mem::{replace, take},
-    ops::Not,
+    ops::{Not, Range},
+    str::FromStr,
 };
 
-use crate::state::{NamedField, Perspective, Piece, PlayerColor, PlayerTurn, PosRange, Translate};
+use crate::bitboard::BitState;
+use crate::notation::NamedMove;
+use crate::rules::RuleSet;
+use crate::state::{NamedField, Piece, PlayerColor, PlayerTurn, Translate};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ClientMsg {
-    pub from: usize,
-    pub to: usize,
-    pub card: &'static str,
+    pub mv: Move,
 }
 
 impl ClientMsg {
     pub fn format_litama(self, match_id: String, token: String, active_eq_red: bool) -> String {
-        let from: NamedField = Perspective::range()
-            .nth(self.from)
-            .unwrap()
-            .translate(active_eq_red);
-        let to: NamedField = Perspective::range()
-            .nth(self.to)
-            .unwrap()
-            .translate(active_eq_red);
-
-        format!("move {match_id} {token} {} {from}{to}", self.card)
+        let m: NamedMove = self.mv.translate(active_eq_red);
+        format!("move {match_id} {token} {} {}", m.card_name(), m.squares())
     }
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "messageType")]
 #[serde(rename_all = "camelCase")]
@@ -66,6 +84,9 @@ pub enum LitamaMsg {
     Error {
         error: String,
         query: String,
+        // set when a move was rejected, so that bots do not need to match on `error`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<MoveError>,
     },
 }
 
@@ -98,7 +119,17 @@ pub struct ExtraState {
     pub starting_cards: Cards,
     pub moves: Vec<String>,
     pub board: String,
+    // "blue", "red", "draw" or "none" while the game is in progress
     pub winner: String,
+    // older servers do not send the rules
+    #[serde(default)]
+    pub rules: RuleSet,
+    // opening book moves for the player to move, like `boar:d1d2`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub book_moves: Vec<String>,
+    // the custom cards of this match, clients register them before reading the state
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_cards: Vec<cards::CardDef>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -134,23 +165,23 @@ pub struct Cards {
     pub side: String,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Color {
     Blue,
     Red,
 }
 
-pub fn card_to_pos(name: String) -> usize {
-    CARDS.iter().position(|c| c.0 == name).unwrap()
+pub fn card_to_pos(name: String) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    Ok(cards::find_card(&name).ok_or(MoveError::UnknownCard)?)
 }
 
-fn player_card_to_pos(name: Vec<String>) -> [usize; 2] {
-    name.into_iter()
+fn player_card_to_pos(name: Vec<String>) -> Result<[usize; 2], Box<dyn Error + Send + Sync>> {
+    let cards = name
+        .into_iter()
         .map(card_to_pos)
-        .collect::<Vec<_>>()
-        .try_into()
-        .unwrap()
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(cards.try_into().map_err(|_| "expected two cards")?)
 }
 
 fn collect_array<T, const N: usize>(iter: impl IntoIterator<Item = T>) -> [T; N] {
@@ -162,6 +193,8 @@ fn collect_array<T, const N: usize>(iter: impl IntoIterator<Item = T>) -> [T; N]
 }
 
 pub static DEFAULT_BOARD: &str = "1121100000000000000033433";
+// same as `DEFAULT_BOARD` with the wind spirit in the middle
+pub static WIND_BOARD: &str = "1121100000005000000033433";
 
 pub fn board_from_str(board: &str) -> [Option<Piece<PlayerColor>>; 25] {
     collect_array(board.chars().map(|c| {
@@ -171,35 +204,215 @@ pub fn board_from_str(board: &str) -> [Option<Piece<PlayerColor>>; 25] {
             Some(state::Piece(state::PlayerColor::BLUE, PieceKind::King)),
             Some(state::Piece(state::PlayerColor::RED, PieceKind::Pawn)),
             Some(state::Piece(state::PlayerColor::RED, PieceKind::King)),
+            Some(state::Piece(state::PlayerColor::BLUE, PieceKind::Spirit)),
         ][c.to_digit(10).unwrap() as usize]
     }))
 }
 
+// inverse of `board_from_str`
+pub fn board_to_str(board: &[Option<Piece<PlayerColor>>; 25]) -> String {
+    board
+        .iter()
+        .map(|p| match p {
+            None => '0',
+            Some(Piece(PlayerColor::BLUE, PieceKind::Pawn)) => '1',
+            Some(Piece(PlayerColor::BLUE, PieceKind::King)) => '2',
+            Some(Piece(PlayerColor::RED, PieceKind::Pawn)) => '3',
+            Some(Piece(PlayerColor::RED, PieceKind::King)) => '4',
+            Some(Piece(_, PieceKind::Spirit)) => '5',
+        })
+        .collect()
+}
+
 impl state::State<NamedField, PlayerColor> {
-    pub fn from_state(extra: ExtraState) -> Self {
-        crate::state::State {
+    // fails on unknown cards, custom cards have to be registered first
+    pub fn from_state(extra: ExtraState) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if extra.board.len() != 25 || !extra.board.chars().all(|c| ('0'..='5').contains(&c)) {
+            return Err("board must be 25 digits from 0 to 5".into());
+        }
+        Ok(crate::state::State {
             board: board_from_str(&extra.board),
-            table_card: card_to_pos(extra.cards.side),
+            table_card: card_to_pos(extra.cards.side)?,
             cards: HashMap::from_iter([
                 (
                     state::PlayerColor::RED,
-                    player_card_to_pos(extra.cards.players.red),
+                    player_card_to_pos(extra.cards.players.red)?,
                 ),
                 (
                     state::PlayerColor::BLUE,
-                    player_card_to_pos(extra.cards.players.blue),
+                    player_card_to_pos(extra.cards.players.blue)?,
                 ),
             ]),
             active_eq_red: extra.current_turn == Color::Red,
             _p: std::marker::PhantomData::<NamedField>,
+        })
+    }
+
+    // starting position with the cards in the order blue1, blue2, red1, red2, side
+    // the wind spirit is only used when one of the cards can move it
+    pub fn new_game(cards: [usize; 5]) -> Self {
+        Self::new_game_with(cards, &RuleSet::DEFAULT)
+    }
+
+    // same as `new_game`, but the starting player comes from `rules`
+    pub fn new_game_with(cards: [usize; 5], rules: &RuleSet) -> Self {
+        let board = match cards.iter().any(|&c| !cards::spirit_moves(c).is_empty()) {
+            true => WIND_BOARD,
+            false => DEFAULT_BOARD,
+        };
+        let mut res = Self::from_board(board_from_str(board), cards);
+        res.active_eq_red = rules.red_starts(cards[4]);
+        res
+    }
+
+    // same as `new_game`, but with a custom board
+    pub fn from_board(board: [Option<Piece<PlayerColor>>; 25], cards: [usize; 5]) -> Self {
+        crate::state::State {
+            board,
+            table_card: cards[4],
+            cards: HashMap::from_iter([
+                (state::PlayerColor::BLUE, [cards[0], cards[1]]),
+                (state::PlayerColor::RED, [cards[2], cards[3]]),
+            ]),
+            // side card determines starting player
+            active_eq_red: cards::card(cards[4]).2 == PlayerColor::RED,
+            _p: std::marker::PhantomData::<NamedField>,
         }
     }
+
+    // compact position string like "1121100000000000000033433 ox,boar crab,horse eel blue"
+    // the parts are board, blue cards, red cards, table card and the player to move
+    pub fn to_fen(&self) -> String {
+        let hand = |player| {
+            let [a, b] = self.cards[&player];
+            format!("{},{}", cards::card(a).0, cards::card(b).0)
+        };
+        format!(
+            "{} {} {} {} {}",
+            board_to_str(&self.board),
+            hand(PlayerColor::BLUE),
+            hand(PlayerColor::RED),
+            cards::card(self.table_card).0,
+            ["blue", "red"][self.active_eq_red as usize]
+        )
+    }
+
+    // inverse of `to_fen`
+    pub fn from_fen(fen: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let [board, blue, red, side, turn]: [&str; 5] = fen
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| "expected 5 parts")?;
+        if board.len() != 25 || !board.chars().all(|c| ('0'..='5').contains(&c)) {
+            return Err("board must be 25 digits from 0 to 5".into());
+        }
+        let find_card = |name: &str| cards::find_card(name).ok_or("unknown card name");
+        let hand = |cards: &str| -> Result<[usize; 2], Box<dyn Error + Send + Sync>> {
+            let (a, b) = cards.split_once(',').ok_or("expected two cards")?;
+            Ok([find_card(a)?, find_card(b)?])
+        };
+        let active_eq_red = match turn {
+            "blue" => false,
+            "red" => true,
+            _ => return Err("expected blue or red to move".into()),
+        };
+        Ok(crate::state::State {
+            board: board_from_str(board),
+            table_card: find_card(side)?,
+            cards: HashMap::from_iter([
+                (state::PlayerColor::BLUE, hand(blue)?),
+                (state::PlayerColor::RED, hand(red)?),
+            ]),
+            active_eq_red,
+            _p: std::marker::PhantomData::<NamedField>,
+        })
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+// a single (card, from, to) combination with positions in the perspective of the active player
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Move {
+    pub card: usize,
+    pub from: usize,
+    pub to: usize,
+    // where the wind spirit goes after the piece moved, only for cards with spirit moves
+    pub spirit: Option<usize>,
+}
+
+impl Move {
+    // a player that can not move any piece passes a card instead, this is written as `from == to`
+    pub fn pass(card: usize) -> Self {
+        Move {
+            card,
+            from: 0,
+            to: 0,
+            spirit: None,
+        }
+    }
+
+    pub fn is_pass(&self) -> bool {
+        self.from == self.to
+    }
+}
+
+/// Why a move was rejected, serialized as a camelCase code like `"tooFar"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MoveError {
+    UnknownCard,
+    NotYourPiece,
+    OntoOwnPiece,
+    TooFar,
+    InvalidForCard,
+    CardNotInHand,
+    CanNotPass,
+    SpiritWhilePassing,
+    SpiritOntoKing,
+    CaptureSpirit,
+    SpiritMoveMissing,
+    SpiritMoveNotAllowed,
+    NoSpirit,
+    SpiritTooFar,
+    InvalidSpiritMove,
+    NotYourTurn,
+    NotInProgress,
+    SelfCheck,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MoveError::UnknownCard => "unknown card name",
+            MoveError::NotYourPiece => "can only move your own pieces",
+            MoveError::OntoOwnPiece => "can not move onto your own piece",
+            MoveError::TooFar => "move too far",
+            MoveError::InvalidForCard => "invalid move for card",
+            MoveError::CardNotInHand => "you do not have that card",
+            MoveError::CanNotPass => "can only pass when no piece can move",
+            MoveError::SpiritWhilePassing => "can not move the wind spirit when passing",
+            MoveError::SpiritOntoKing => "the wind spirit can not move onto a king",
+            MoveError::CaptureSpirit => "can not capture the wind spirit",
+            MoveError::SpiritMoveMissing => "this card also moves the wind spirit",
+            MoveError::SpiritMoveNotAllowed => "this card does not move the wind spirit",
+            MoveError::NoSpirit => "there is no wind spirit",
+            MoveError::SpiritTooFar => "wind spirit move too far",
+            MoveError::InvalidSpiritMove => "invalid wind spirit move for card",
+            MoveError::NotYourTurn => "it is not your turn",
+            MoveError::NotInProgress => "game must be in progress",
+            MoveError::SelfCheck => "can not move into check",
+        })
+    }
+}
+
+impl Error for MoveError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PieceKind {
     Pawn,
     King,
+    // the wind spirit belongs to nobody, so the player of this piece has no meaning
+    Spirit,
 }
 
 pub fn get_offset(pos: usize, from: usize) -> Option<usize> {
@@ -230,9 +443,9 @@ const KING: Option<Piece> = Some(Piece(PlayerTurn::ACTIVE, PieceKind::King));
 const OPP_KING: Option<Piece> = Some(Piece(PlayerTurn::WAITING, PieceKind::King));
 
 // check for mate assuming that there is no check on the active player
-pub fn is_mate(game: &mut state::State) -> bool {
+pub fn is_mate(game: &mut state::State, rules: &RuleSet) -> bool {
     let opp_king = game.board.iter().position(|&p| p == OPP_KING).unwrap();
-    if let Some(offset) = get_offset(opp_king, 22) {
+    if let Some(offset) = rules.temple().and_then(|t| get_offset(opp_king, t)) {
         if game.cards[&PlayerTurn::WAITING]
             .iter()
             .any(|c| in_card(offset, *c))
@@ -240,14 +453,41 @@ pub fn is_mate(game: &mut state::State) -> bool {
             return true;
         }
     }
-    !(0..25).any(|from| (0..25).any(|to| check_move(game, from, to).is_some()))
+    legal_moves_with(game, rules).next().is_none()
+}
+
+// a player can only pass when none of their pieces has a move that `rules` allow, not even the wind spirit
+// without self check, a player whose king is in check can not pass
+pub fn can_pass_with(game: &state::State, rules: &RuleSet) -> bool {
+    match BitState::try_from(game) {
+        Ok(bits) => bits.can_pass_with(rules),
+        Err(_) => wind::can_pass(game, rules),
+    }
+}
+
+// every move that `check_move` would allow, listed separately for each card
+// passes are included when every piece move walks into check
+pub fn legal_moves(game: &state::State) -> impl Iterator<Item = Move> {
+    legal_moves_with(game, &RuleSet::DEFAULT)
+}
+
+// same as `legal_moves`, moves into check are included when `rules` allow them
+pub fn legal_moves_with(game: &state::State, rules: &RuleSet) -> impl Iterator<Item = Move> {
+    let moves: Vec<_> = match BitState::try_from(game) {
+        Ok(bits) if rules.self_check => bits.moves().collect(),
+        Ok(bits) => bits.legal_moves().collect(),
+        Err(_) => wind::legal_moves(game, rules),
+    };
+    moves.into_iter()
 }
 
 pub fn check_move(game: &mut state::State, from: usize, to: usize) -> Option<&'static str> {
     let piece = game.board[from]?;
-    (piece.0 == PlayerTurn::ACTIVE).as_option()?;
+    (piece.0 == PlayerTurn::ACTIVE && piece.1 != PieceKind::Spirit).as_option()?;
     let other = game.board[to];
-    (other.is_none() || other.unwrap().0 == PlayerTurn::WAITING).as_option()?;
+    other
+        .is_none_or(|x| x.0 == PlayerTurn::WAITING && x.1 != PieceKind::Spirit)
+        .as_option()?;
 
     let piece = take(&mut game.board[from]);
     let tmp = replace(&mut game.board[to], piece);
@@ -260,25 +500,26 @@ pub fn check_move(game: &mut state::State, from: usize, to: usize) -> Option<&'s
     game.cards[&PlayerTurn::ACTIVE]
         .iter()
         .find(|x| in_card(offset, **x))
-        .map(|c| CARDS[*c].0)
+        .map(|c| cards::card(*c).0)
 }
 
-fn is_check(game: &state::State) -> bool {
-    let king = game.board.iter().position(|&p| p == KING).unwrap();
+pub(crate) fn is_check(game: &state::State) -> bool {
+    let Some(king) = game.board.iter().position(|&p| p == KING) else {
+        return false;
+    };
     game.cards[&PlayerTurn::WAITING]
         .iter()
         .any(|c| is_check_card(game, king, *c))
 }
 
 fn is_check_card(game: &state::State, from: usize, card: usize) -> bool {
-    CARDS[card]
+    cards::card(card)
         .1
         .iter()
-        .map(|&offset| apply_offset(offset, from))
-        .flatten()
+        .filter_map(|&offset| apply_offset(offset, from))
         .any(|pos| {
             let piece = game.board[pos];
-            piece.is_some() && piece.unwrap().0 == PlayerTurn::WAITING
+            piece.is_some_and(|x| x.0 == PlayerTurn::WAITING && x.1 != PieceKind::Spirit)
         })
 }
 
@@ -287,7 +528,7 @@ fn diff(a: usize, b: usize) -> usize {
 }
 
 pub fn in_card(offset: usize, card: usize) -> bool {
-    CARDS[card].1.contains(&offset)
+    cards::card(card).1.contains(&offset)
 }
 
 // 0 1 2 3 4  00
@@ -313,4 +554,101 @@ pub static CARDS: &[(&str, &[usize], PlayerColor)] = &[
     ("rooster", &[8, 11, 13, 16], PlayerColor::RED),
     ("eel", &[6, 13, 16], PlayerColor::BLUE),
     ("cobra", &[8, 11, 18], PlayerColor::RED),
+    // sensei's path expansion
+    ("bear", &[6, 7, 18], PlayerColor::BLUE),
+    ("dog", &[6, 11, 16], PlayerColor::BLUE),
+    ("fox", &[8, 13, 18], PlayerColor::RED),
+    ("giraffe", &[5, 9, 17], PlayerColor::BLUE),
+    ("iguana", &[5, 7, 18], PlayerColor::RED),
+    ("kirin", &[1, 3, 22], PlayerColor::RED),
+    ("mouse", &[7, 13, 16], PlayerColor::BLUE),
+    ("otter", &[6, 14, 18], PlayerColor::RED),
+    ("panda", &[7, 8, 16], PlayerColor::RED),
+    ("phoenix", &[6, 8, 10, 14], PlayerColor::BLUE),
+    ("rat", &[7, 11, 18], PlayerColor::RED),
+    ("sable", &[8, 10, 16], PlayerColor::BLUE),
+    // names can not contain spaces, because they are used in the litama protocol
+    ("sea_snake", &[7, 14, 16], PlayerColor::BLUE),
+    ("tanuki", &[7, 9, 16], PlayerColor::BLUE),
+    ("turtle", &[10, 14, 16, 18], PlayerColor::RED),
+    ("viper", &[7, 10, 18], PlayerColor::RED),
 ];
+
+// sets of cards that a match can draw its five cards from
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CardPool {
+    Base,
+    SenseisPath,
+    Custom,
+}
+
+impl CardPool {
+    // card indices, `Custom` contains all cards that were registered at runtime
+    // like the Way of the Wind cards, which are not built in
+    pub fn cards(self) -> Range<usize> {
+        match self {
+            CardPool::Base => 0..16,
+            CardPool::SenseisPath => 16..32,
+            CardPool::Custom => CARDS.len()..cards::card_count(),
+        }
+    }
+}
+
+impl FromStr for CardPool {
+    type Err = Box<dyn Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "base" => Ok(CardPool::Base),
+            "senseis_path" => Ok(CardPool::SenseisPath),
+            "custom" => Ok(CardPool::Custom),
+            _ => Err("unknown card pool".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+
+    #[test]
+    fn fen_round_trip() {
+        let start = State::<NamedField, PlayerColor>::new_game([0, 1, 2, 3, 4]);
+        let fen = start.to_fen();
+        assert_eq!(
+            fen,
+            "1121100000000000000033433 ox,boar horse,elephant crab blue"
+        );
+
+        let state = State::<NamedField, PlayerColor>::from_fen(&fen).unwrap();
+        assert_eq!(state.board, start.board);
+        assert_eq!(state.cards, start.cards);
+        assert_eq!(state.table_card, start.table_card);
+        assert_eq!(state.active_eq_red, start.active_eq_red);
+
+        let wind = "1121100000005000000033433 ox,boar horse,elephant crab red";
+        let state = State::<NamedField, PlayerColor>::from_fen(wind).unwrap();
+        assert_eq!(state.to_fen(), wind);
+    }
+
+    #[test]
+    fn fen_invalid() {
+        let fens = [
+            "",
+            "1121100000000000000033433 ox,boar horse,elephant crab",
+            "112110000000000000003343 ox,boar horse,elephant crab blue",
+            "1121100000000000000033436 ox,boar horse,elephant crab blue",
+            "1121100000000000000033433 ox boar horse,elephant crab blue",
+            "1121100000000000000033433 ox,boar horse,elephant cat blue",
+            "1121100000000000000033433 ox,boar horse,elephant crab green",
+        ];
+        for fen in fens {
+            assert!(
+                State::<NamedField, PlayerColor>::from_fen(fen).is_err(),
+                "{}",
+                fen
+            );
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Monte Carlo tree search with UCT and random playouts.
+//!
+//! This is weaker than the alpha-beta engine for the same time, but its strength
+//! scales smoothly with the number of iterations and it supports the wind spirit.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    legal_moves_with,
+    rules::RuleSet,
+    state::{Piece, PlayerTurn, State},
+    Move, PieceKind,
+};
+
+// playouts that take longer than this are scored as a draw
+const MAX_PLAYOUT: u32 = 200;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Budget {
+    Iterations(u32),
+    Time(Duration),
+}
+
+#[derive(Clone, Debug)]
+pub struct MctsResult {
+    pub best: Option<Move>,
+    pub iterations: u32,
+    // how often the best move was visited, and its average result between 0 and 1
+    pub visits: u32,
+    pub win_rate: f64,
+}
+
+pub struct Mcts {
+    // weight of the exploration term in UCT
+    pub exploration: f64,
+    // stop playouts after this many moves and use the material balance instead
+    pub playout_depth: Option<u32>,
+    pub rules: RuleSet,
+    rng: u64,
+}
+
+struct Node {
+    mv: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    visits: u32,
+    // total result for the player that made `mv`
+    wins: f64,
+}
+
+impl Default for Mcts {
+    fn default() -> Self {
+        Self::new(0x9e37_79b9_7f4a_7c15)
+    }
+}
+
+impl Mcts {
+    pub fn new(seed: u64) -> Self {
+        Mcts {
+            exploration: std::f64::consts::SQRT_2,
+            playout_depth: None,
+            rules: RuleSet::DEFAULT,
+            // xorshift gets stuck on zero
+            rng: seed | 1,
+        }
+    }
+
+    pub fn search(&mut self, state: &State, budget: Budget) -> MctsResult {
+        let deadline = match budget {
+            Budget::Time(time) => Some(Instant::now() + time),
+            Budget::Iterations(_) => None,
+        };
+        let mut tree = vec![Node {
+            mv: None,
+            parent: None,
+            children: Vec::new(),
+            untried: self.moves(state),
+            visits: 0,
+            wins: 0.,
+        }];
+
+        let mut iterations = 0;
+        loop {
+            match budget {
+                Budget::Iterations(n) if iterations >= n => break,
+                Budget::Time(_) if deadline.is_some_and(|d| Instant::now() >= d) => break,
+                _ => {}
+            }
+            if tree[0].untried.is_empty() && tree[0].children.is_empty() {
+                break;
+            }
+            self.iterate(&mut tree, state);
+            iterations += 1;
+        }
+
+        let best = tree[0].children.iter().max_by_key(|&&c| tree[c].visits);
+        match best {
+            Some(&c) => MctsResult {
+                best: tree[c].mv,
+                iterations,
+                visits: tree[c].visits,
+                win_rate: tree[c].wins / tree[c].visits as f64,
+            },
+            None => MctsResult {
+                best: None,
+                iterations,
+                visits: 0,
+                win_rate: 0.,
+            },
+        }
+    }
+
+    fn iterate(&mut self, tree: &mut Vec<Node>, root: &State) {
+        let mut state = root.clone();
+        let mut node = 0;
+
+        // selection
+        while tree[node].untried.is_empty() && !tree[node].children.is_empty() {
+            let log_n = (tree[node].visits as f64).ln();
+            node = *tree[node]
+                .children
+                .iter()
+                .max_by(|&&a, &&b| {
+                    let (a, b) = (self.uct(&tree[a], log_n), self.uct(&tree[b], log_n));
+                    a.total_cmp(&b)
+                })
+                .unwrap();
+            state.apply(tree[node].mv.unwrap());
+        }
+
+        // expansion
+        if !tree[node].untried.is_empty() {
+            let i = self.random(tree[node].untried.len());
+            let m = tree[node].untried.swap_remove(i);
+            state.apply(m);
+            tree.push(Node {
+                mv: Some(m),
+                parent: Some(node),
+                children: Vec::new(),
+                untried: self.moves(&state),
+                visits: 0,
+                wins: 0.,
+            });
+            let child = tree.len() - 1;
+            tree[node].children.push(child);
+            node = child;
+        }
+
+        // the result for the active player of `state`, which is the opponent of whoever moved into `node`
+        let mut result = 1. - self.playout(state);
+        let mut current = Some(node);
+        while let Some(i) = current {
+            tree[i].visits += 1;
+            tree[i].wins += result;
+            result = 1. - result;
+            current = tree[i].parent;
+        }
+    }
+
+    fn uct(&self, node: &Node, log_n: f64) -> f64 {
+        let visits = node.visits as f64;
+        node.wins / visits + self.exploration * (log_n / visits).sqrt()
+    }
+
+    // play random moves, returns the result for the player that is active at the start
+    fn playout(&mut self, mut state: State) -> f64 {
+        let limit = self.playout_depth.unwrap_or(MAX_PLAYOUT).min(MAX_PLAYOUT);
+        for ply in 0..=limit {
+            let flip = |x: f64| if ply % 2 == 0 { x } else { 1. - x };
+            if let Some(outcome) = state.outcome_with(&self.rules) {
+                return flip((outcome.winner == PlayerTurn::ACTIVE) as u8 as f64);
+            }
+            if ply == limit {
+                return match self.playout_depth {
+                    Some(_) => flip(evaluate(&state)),
+                    None => 0.5,
+                };
+            }
+            let moves = self.moves(&state);
+            if moves.is_empty() {
+                return flip(0.);
+            }
+            state.apply(moves[self.random(moves.len())]);
+        }
+        unreachable!()
+    }
+
+    fn moves(&self, state: &State) -> Vec<Move> {
+        if state.outcome_with(&self.rules).is_some() {
+            return Vec::new();
+        }
+        legal_moves_with(state, &self.rules).collect()
+    }
+
+    fn random(&mut self, n: usize) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng % n as u64) as usize
+    }
+}
+
+// chance to win for the active player, from the difference in pawns
+fn evaluate(state: &State) -> f64 {
+    let pawns = |player| {
+        let pawn = Some(Piece(player, PieceKind::Pawn));
+        state.board.iter().filter(|&&p| p == pawn).count() as f64
+    };
+    let diff = pawns(PlayerTurn::ACTIVE) - pawns(PlayerTurn::WAITING);
+    1. / (1. + (-diff / 2.).exp())
+}
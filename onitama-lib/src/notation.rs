@@ -0,0 +1,200 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::{
+    cards,
+    state::{NamedField, Perspective, Translate},
+    Move, MoveError,
+};
+
+/// A move with squares named from blue's perspective, written as `card:a1b2`.
+///
+/// This is the notation used in the move history, unlike [`Move`] it does not
+/// depend on which player is active.
+/// Cards that also move the wind spirit add its target square, like `card:a1b2c3`.
+/// A pass is written as `card:pass`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NamedMove {
+    pub card: usize,
+    pub from: NamedField,
+    pub to: NamedField,
+    pub spirit: Option<NamedField>,
+}
+
+impl NamedMove {
+    // parse the separate card name and squares, like in the litama `move` command
+    pub fn from_parts(card: &str, from_to: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let card = cards::find_card(card).ok_or(MoveError::UnknownCard)?;
+        if from_to == "pass" {
+            return Ok(Self::pass(card));
+        }
+        if ![4, 6].contains(&from_to.len()) || !from_to.is_ascii() {
+            return Err("move has unexpected len or is not ascii".into());
+        }
+        Ok(Self {
+            card,
+            from: from_to[..2].parse()?,
+            to: from_to[2..4].parse()?,
+            spirit: from_to
+                .get(4..)
+                .filter(|x| !x.is_empty())
+                .map(str::parse)
+                .transpose()?,
+        })
+    }
+
+    // same as `Move::pass`, the squares are the same and have no meaning
+    pub fn pass(card: usize) -> Self {
+        let a1 = NamedField { col: 'a', row: '1' };
+        Self {
+            card,
+            from: a1,
+            to: a1,
+            spirit: None,
+        }
+    }
+
+    pub fn is_pass(&self) -> bool {
+        self.from == self.to
+    }
+
+    pub fn card_name(&self) -> &'static str {
+        cards::card(self.card).0
+    }
+
+    // the squares without the card, like `a1b2` or `a1b2c3`, or `pass`
+    pub fn squares(&self) -> String {
+        if self.is_pass() {
+            return "pass".to_owned();
+        }
+        match self.spirit {
+            Some(spirit) => format!("{}{}{spirit}", self.from, self.to),
+            None => format!("{}{}", self.from, self.to),
+        }
+    }
+}
+
+impl fmt::Display for NamedMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.card_name(), self.squares())
+    }
+}
+
+impl FromStr for NamedMove {
+    type Err = Box<dyn Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (card, from_to) = s.split_once(':').ok_or("expected card:move")?;
+        Self::from_parts(card, from_to)
+    }
+}
+
+impl Translate<Move> for NamedMove {
+    fn translate(self, active_eq_red: bool) -> Move {
+        if self.is_pass() {
+            return Move::pass(self.card);
+        }
+        let from: Perspective = self.from.translate(active_eq_red);
+        let to: Perspective = self.to.translate(active_eq_red);
+        let spirit = self.spirit.map(|x| {
+            let x: Perspective = x.translate(active_eq_red);
+            x.index()
+        });
+        Move {
+            card: self.card,
+            from: from.index(),
+            to: to.index(),
+            spirit,
+        }
+    }
+}
+
+impl Translate<NamedMove> for Move {
+    fn translate(self, active_eq_red: bool) -> NamedMove {
+        if self.is_pass() {
+            return NamedMove::pass(self.card);
+        }
+        NamedMove {
+            card: self.card,
+            from: Perspective::from_index(self.from).translate(active_eq_red),
+            to: Perspective::from_index(self.to).translate(active_eq_red),
+            spirit: self
+                .spirit
+                .map(|x| Perspective::from_index(x).translate(active_eq_red)),
+        }
+    }
+}
+
+/// Move history, written as a comma separated list like `elephant:a1b2,boar:a2c3`.
+///
+/// The empty string is an empty history.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MoveList(pub Vec<NamedMove>);
+
+impl fmt::Display for MoveList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, m) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{m}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MoveList {
+    type Err = Box<dyn Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(Self::default());
+        }
+        let moves = s.split(',').map(str::parse).collect::<Result<_, _>>()?;
+        Ok(Self(moves))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for text in ["boar:d1d2", "elephant:c5b4", "ox:pass", "eel:a1e5"] {
+            let m: NamedMove = text.parse().unwrap();
+            assert_eq!(m.to_string(), text);
+            for active_eq_red in [false, true] {
+                let mv: Move = m.translate(active_eq_red);
+                let back: NamedMove = mv.translate(active_eq_red);
+                assert_eq!(back, m);
+            }
+        }
+        let list = "boar:d1d2,elephant:c5b4,ox:pass";
+        assert_eq!(list.parse::<MoveList>().unwrap().to_string(), list);
+        assert_eq!("".parse::<MoveList>().unwrap(), MoveList::default());
+    }
+
+    #[test]
+    fn perspective() {
+        let m: NamedMove = "boar:a1b2".parse().unwrap();
+        let blue: Move = m.translate(false);
+        let red: Move = m.translate(true);
+        assert_eq!((blue.from, blue.to), (20, 16));
+        assert_eq!((red.from, red.to), (4, 8));
+    }
+
+    #[test]
+    fn invalid() {
+        for text in [
+            "boar",
+            "dolphin:a1b2",
+            "boar:a1",
+            "boar:a1b6",
+            "boar:f1b2",
+            "boar:a1b2c",
+        ] {
+            assert!(text.parse::<NamedMove>().is_err(), "{}", text);
+        }
+        assert!("boar:a1b2,".parse::<MoveList>().is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use std::{convert::TryFrom, error::Error};
+
+use crate::{
+    bitboard::BitState,
+    card_to_pos, cards, check_move, get_offset, in_card, is_mate, legal_moves,
+    rules::RuleSet,
+    state::{Perspective, PlayerTurn, State},
+    Move,
+};
+
+// node counts for the starting position, in the order blue1, blue2, red1, red2, side
+// the counts are for depth 1, 2, 3, ...
+pub static REFERENCE: &[([&str; 5], &[u64])] = &[
+    (
+        ["ox", "boar", "horse", "elephant", "crab"],
+        &[10, 130, 1969, 27185, 446502, 6639522],
+    ),
+    (
+        ["tiger", "dragon", "frog", "rabbit", "crab"],
+        &[11, 82, 912, 7894, 83040, 849538],
+    ),
+    (
+        ["monkey", "crane", "mantis", "eel", "cobra"],
+        &[12, 156, 1873, 21738, 274652, 3329599],
+    ),
+    (
+        ["goose", "rooster", "elephant", "tiger", "ox"],
+        &[8, 100, 1144, 14852, 200956, 2737212],
+    ),
+];
+
+// number of positions after exactly `depth` moves, finished games are not continued
+// fails for positions with the wind spirit
+pub fn perft(state: &State, depth: u32) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    Ok(perft_bits(BitState::try_from(state)?, depth))
+}
+
+pub fn perft_bits(state: BitState, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    if state.is_lost() {
+        return 0;
+    }
+    if depth == 1 {
+        return state.legal_moves().count() as u64;
+    }
+    state
+        .legal_moves()
+        .map(|m| perft_bits(state.play(m), depth - 1))
+        .sum()
+}
+
+// perft for each move separately
+pub fn divide(state: &State, depth: u32) -> Result<Vec<(Move, u64)>, Box<dyn Error + Send + Sync>> {
+    let state = BitState::try_from(state)?;
+    Ok(state
+        .legal_moves()
+        .map(|m| (m, perft_bits(state.play(m), depth.saturating_sub(1))))
+        .collect())
+}
+
+// same as `perft`, but only uses the slow `State` functions that the server and client use
+// panics when `check_move`, `make_move` and `is_mate` do not agree with each other
+pub fn perft_slow(state: &State, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    if state.outcome().is_some() {
+        return 0;
+    }
+
+    let mut game = state.clone();
+    let mut count = 0;
+    let mut any_move = false;
+    for from in 0..25 {
+        for to in 0..25 {
+            if check_move(&mut game, from, to).is_none() {
+                continue;
+            }
+            any_move = true;
+
+            let offset = get_offset(to, from).unwrap();
+            for &card in &state.cards[&PlayerTurn::ACTIVE] {
+                if !in_card(offset, card) {
+                    continue;
+                }
+                let next = state
+                    .clone()
+                    .make_move(
+                        cards::card(card).0,
+                        Perspective::from_index(from),
+                        Perspective::from_index(to),
+                        &RuleSet::DEFAULT,
+                    )
+                    .expect("make_move rejected a move allowed by check_move");
+                count += perft_slow(&next, depth - 1);
+            }
+        }
+    }
+    if !any_move {
+        // without any piece moves the player has to pass one of the cards
+        let passes: Vec<_> = legal_moves(state).filter(Move::is_pass).collect();
+        for m in &passes {
+            let next = state
+                .clone()
+                .make_pass(cards::card(m.card).0, &RuleSet::DEFAULT)
+                .expect("make_pass rejected a pass allowed by legal_moves");
+            count += perft_slow(&next, depth - 1);
+        }
+        if passes.is_empty() {
+            let mate = is_mate(&mut game, &RuleSet::DEFAULT);
+            assert!(mate, "is_mate is false without any moves");
+        }
+    }
+    count
+}
+
+// run the reference suite up to `max_depth`, returns the first mismatch as (cards, depth, expected, found)
+pub fn check_reference(max_depth: u32) -> Result<(), ([&'static str; 5], u32, u64, u64)> {
+    for (cards, counts) in REFERENCE {
+        let state = State::new_game(cards.map(|c| card_to_pos(c.to_owned()).unwrap())).translate();
+        for (depth, &expected) in (1..=max_depth).zip(counts.iter()) {
+            let found = perft(&state, depth).unwrap();
+            if found != expected {
+                return Err((*cards, depth, expected, found));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference() {
+        assert_eq!(check_reference(4), Ok(()));
+    }
+
+    // too slow for debug builds, run it with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn reference_full() {
+        let depth = REFERENCE
+            .iter()
+            .map(|(_, counts)| counts.len())
+            .max()
+            .unwrap();
+        assert_eq!(check_reference(depth as u32), Ok(()));
+        for (cards, counts) in REFERENCE {
+            let state =
+                State::new_game(cards.map(|c| card_to_pos(c.to_owned()).unwrap())).translate();
+            assert_eq!(perft_slow(&state, 5), counts[4], "{cards:?}");
+        }
+    }
+
+    #[test]
+    fn slow_agrees() {
+        for (cards, counts) in REFERENCE {
+            let state =
+                State::new_game(cards.map(|c| card_to_pos(c.to_owned()).unwrap())).translate();
+            assert_eq!(perft_slow(&state, 3), counts[2], "{cards:?}");
+        }
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let state = State::new_game([0, 1, 2, 3, 4]).translate();
+        let total: u64 = divide(&state, 3).unwrap().iter().map(|(_, n)| n).sum();
+        assert_eq!(total, perft(&state, 3).unwrap());
+    }
+}
@@ -0,0 +1,222 @@
+//! Proof-number search, which proves forced wins and losses instead of estimating them.
+//!
+//! Every search answers one question, like "can the active player win within 9 plies".
+//! [`Solver::solve`] asks these questions with a growing ply limit, so the first proof
+//! that it finds is also the fastest win or the slowest loss.
+
+use std::{convert::TryFrom, error::Error};
+
+use crate::{bitboard::BitState, rules::RuleSet, state::State, Move};
+
+const INF: u32 = u32::MAX;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Proof {
+    // the active player wins in this many plies, whatever the opponent does
+    Win(u32),
+    // the active player loses in this many plies, whatever they do
+    Loss(u32),
+    // there was no proof within the ply limit, or the search ran out of nodes
+    Unknown,
+}
+
+#[derive(Clone, Debug)]
+pub struct Solution {
+    pub proof: Proof,
+    // the fastest win against the longest defence, every move is in the perspective of the player that makes it
+    pub line: Vec<Move>,
+    pub nodes: usize,
+}
+
+pub struct Solver {
+    pub rules: RuleSet,
+    // the search gives up after creating this many nodes in total
+    pub max_nodes: usize,
+}
+
+struct Node {
+    state: BitState,
+    mv: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    ply: u32,
+    // numbers for the question of the search, not for the player to move
+    pn: u32,
+    dn: u32,
+}
+
+// nodes where the attacker moves need one good move, the others need every move to be good
+fn attacker_to_move(ply: u32, win: bool) -> bool {
+    ply.is_multiple_of(2) == win
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver {
+            rules: RuleSet::OFFICIAL,
+            max_nodes: 1 << 20,
+        }
+    }
+}
+
+impl Solver {
+    // fails for positions with the wind spirit
+    pub fn solve_state(
+        &self,
+        state: &State,
+        max_plies: u32,
+    ) -> Result<Solution, Box<dyn Error + Send + Sync>> {
+        Ok(self.solve(BitState::try_from(state)?, max_plies))
+    }
+
+    pub fn solve(&self, state: BitState, max_plies: u32) -> Solution {
+        let mut nodes = 0;
+        for limit in 0..=max_plies {
+            // wins end after a move of the active player, losses after a move of the opponent
+            let win = !limit.is_multiple_of(2);
+            let Some(tree) = self.prove(state, win, limit, &mut nodes) else {
+                break;
+            };
+            if tree[0].pn == 0 {
+                let line = self.line(&tree, win);
+                let proof = match win {
+                    true => Proof::Win(line.len() as u32),
+                    false => Proof::Loss(line.len() as u32),
+                };
+                return Solution { proof, line, nodes };
+            }
+        }
+        Solution {
+            proof: Proof::Unknown,
+            line: Vec::new(),
+            nodes,
+        }
+    }
+
+    fn moves(&self, state: &BitState) -> Vec<Move> {
+        match self.rules.self_check {
+            true => state.moves().collect(),
+            false => state.legal_moves().collect(),
+        }
+    }
+
+    // the player to move has lost when their king is gone or they can not move
+    fn is_lost(&self, state: &BitState) -> bool {
+        state.is_lost_with(&self.rules) || self.moves(state).is_empty()
+    }
+
+    // the node for `state` proves the question when the attacker is not the one that lost
+    fn leaf(&self, state: BitState, ply: u32, attacker_to_move: bool, limit: u32) -> (u32, u32) {
+        match self.is_lost(&state) {
+            true if attacker_to_move => (INF, 0),
+            true => (0, INF),
+            false if ply >= limit => (INF, 0),
+            false => (1, 1),
+        }
+    }
+
+    // the attacker is the active player of the root when proving a win, and the opponent otherwise
+    // returns `None` when the nodes run out before the question is answered
+    fn prove(&self, root: BitState, win: bool, limit: u32, nodes: &mut usize) -> Option<Vec<Node>> {
+        let (pn, dn) = self.leaf(root, 0, win, limit);
+        let mut tree = vec![Node {
+            state: root,
+            mv: None,
+            parent: None,
+            children: Vec::new(),
+            ply: 0,
+            pn,
+            dn,
+        }];
+
+        while tree[0].pn != 0 && tree[0].dn != 0 {
+            if *nodes >= self.max_nodes {
+                return None;
+            }
+
+            // the most proving node
+            let mut node = 0;
+            while !tree[node].children.is_empty() {
+                let or_node = attacker_to_move(tree[node].ply, win);
+                node = *tree[node]
+                    .children
+                    .iter()
+                    .min_by_key(|&&c| match or_node {
+                        true => tree[c].pn,
+                        false => tree[c].dn,
+                    })
+                    .unwrap();
+            }
+
+            let state = tree[node].state;
+            let ply = tree[node].ply + 1;
+            for m in self.moves(&state) {
+                let next = state.play(m);
+                let (pn, dn) = self.leaf(next, ply, attacker_to_move(ply, win), limit);
+                tree.push(Node {
+                    state: next,
+                    mv: Some(m),
+                    parent: Some(node),
+                    children: Vec::new(),
+                    ply,
+                    pn,
+                    dn,
+                });
+                let child = tree.len() - 1;
+                tree[node].children.push(child);
+                *nodes += 1;
+            }
+
+            let mut current = Some(node);
+            while let Some(i) = current {
+                let children = &tree[i].children;
+                let min = |f: fn(&Node) -> u32| children.iter().map(|&c| f(&tree[c])).min();
+                let sum = |f: fn(&Node) -> u32| {
+                    children
+                        .iter()
+                        .fold(0u32, |acc, &c| acc.saturating_add(f(&tree[c])))
+                };
+                let (pn, dn) = match attacker_to_move(tree[i].ply, win) {
+                    true => (min(|n| n.pn).unwrap_or(INF), sum(|n| n.dn)),
+                    false => (sum(|n| n.pn), min(|n| n.dn).unwrap_or(INF)),
+                };
+                tree[i].pn = pn;
+                tree[i].dn = dn;
+                current = tree[i].parent;
+            }
+        }
+        Some(tree)
+    }
+
+    // the attacker takes the shortest proof and the defender the longest
+    fn line(&self, tree: &[Node], win: bool) -> Vec<Move> {
+        let mut distance = vec![0; tree.len()];
+        // children always come after their parent
+        for i in (0..tree.len()).rev() {
+            let or_node = attacker_to_move(tree[i].ply, win);
+            let proved = tree[i].children.iter().filter(|&&c| tree[c].pn == 0);
+            let child = proved.map(|&c| distance[c]);
+            distance[i] = match or_node {
+                true => child.min().map_or(0, |d| d + 1),
+                false => child.max().map_or(0, |d| d + 1),
+            };
+        }
+
+        let mut line = Vec::new();
+        let mut node = 0;
+        while !tree[node].children.is_empty() {
+            let or_node = attacker_to_move(tree[node].ply, win);
+            let proved = tree[node].children.iter().filter(|&&c| tree[c].pn == 0);
+            let next = match or_node {
+                true => proved.min_by_key(|&&c| distance[c]),
+                false => proved.max_by_key(|&&c| distance[c]),
+            };
+            let Some(&next) = next else {
+                break;
+            };
+            line.push(tree[next].mv.unwrap());
+            node = next;
+        }
+        line
+    }
+}
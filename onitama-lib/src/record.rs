@@ -0,0 +1,368 @@
+use std::{convert::TryInto, error::Error, fmt, str::FromStr};
+
+use crate::{
+    board_from_str, cards,
+    draw::DrawTracker,
+    notation::NamedMove,
+    rules::RuleSet,
+    state::{NamedField, PlayerColor, State},
+    variant::Variant,
+    Color, Sides,
+};
+
+/// A complete game in a PGN-like text format.
+///
+/// ```text
+/// [Blue "alice"]
+/// [Red "bob"]
+/// [Cards "ox,boar,horse,elephant,crab"]
+/// [Started "1700000000000"]
+/// [Result "none"]
+///
+/// 1. boar:d1d2 {1700000004000} elephant:c5b4 {1700000009000}
+/// 2. crab:d2d3
+/// ```
+///
+/// The cards are in the order blue1, blue2, red1, red2, side. `Board` uses the same
+/// encoding as `DEFAULT_BOARD` and is only needed for other starting positions,
+/// without it the game starts like [`State::new_game`].
+/// `Variant` and `Rules` use the same text as [`Variant`] and [`RuleSet`], they are
+/// only written for games that are not standard.
+/// `Result` is `blue`, `red`, `draw` or `none` for games that did not finish.
+/// `Started` and the `{..}` after a move are optional timestamps in milliseconds
+/// since the unix epoch. Unknown tags are ignored.
+#[derive(Debug)]
+pub struct GameRecord {
+    pub names: Sides<String>,
+    pub cards: [usize; 5],
+    pub board: Option<String>,
+    pub started: Option<u64>,
+    pub variant: Variant,
+    pub rules: RuleSet,
+    pub moves: Vec<RecordMove>,
+    // `None` if the game did not finish
+    pub result: Option<GameResult>,
+}
+
+type Replayed = (State<NamedField, PlayerColor>, Option<GameResult>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    Win(Color),
+    // by repetition or the ply limit of the rules
+    Draw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordMove {
+    pub mv: NamedMove,
+    pub time: Option<u64>,
+}
+
+impl GameRecord {
+    // the position before the first move
+    pub fn start(&self) -> State<NamedField, PlayerColor> {
+        match &self.board {
+            Some(board) => {
+                let mut res = State::from_board(board_from_str(board), self.cards);
+                res.active_eq_red = self.rules.red_starts(self.cards[4]);
+                res
+            }
+            None => State::new_game_with(self.cards, &self.rules),
+        }
+    }
+
+    // play all moves from the start, fails on the first illegal move or a move after the game ended
+    pub fn replay(&self) -> Result<State<NamedField, PlayerColor>, Box<dyn Error + Send + Sync>> {
+        Ok(self.play()?.0)
+    }
+
+    // the result that the moves lead to, this does not look at `result`
+    pub fn final_result(&self) -> Result<Option<GameResult>, Box<dyn Error + Send + Sync>> {
+        Ok(self.play()?.1)
+    }
+
+    // the position after the last move and the result
+    fn play(&self) -> Result<Replayed, Box<dyn Error + Send + Sync>> {
+        let mut state = self.start();
+        let mut draws = DrawTracker::new(&state, &self.rules);
+        let mut result = self.result_of(&state, &draws);
+        for m in &self.moves {
+            if result.is_some() {
+                return Err("game is already over".into());
+            }
+            let next = self
+                .variant
+                .make_move(state.translate(), m.mv, &self.rules)?;
+            state = next.translate();
+            draws.push(&state);
+            result = self.result_of(&state, &draws);
+        }
+        Ok((state, result))
+    }
+
+    fn result_of(
+        &self,
+        state: &State<NamedField, PlayerColor>,
+        draws: &DrawTracker,
+    ) -> Option<GameResult> {
+        match self.variant.outcome(state, &self.rules) {
+            Some(o) => Some(GameResult::Win(
+                [Color::Blue, Color::Red][o.winner.is_red as usize],
+            )),
+            None => draws.draw().map(|_| GameResult::Draw),
+        }
+    }
+}
+
+// `blue`, `red`, `draw` or `none`, like the `Result` tag and `ExtraState::winner`
+pub fn parse_result(s: &str) -> Result<Option<GameResult>, Box<dyn Error + Send + Sync>> {
+    match s {
+        "blue" => Ok(Some(GameResult::Win(Color::Blue))),
+        "red" => Ok(Some(GameResult::Win(Color::Red))),
+        "draw" => Ok(Some(GameResult::Draw)),
+        "none" => Ok(None),
+        _ => Err("unknown result".into()),
+    }
+}
+
+fn write_tag(f: &mut fmt::Formatter<'_>, name: &str, value: &str) -> fmt::Result {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    writeln!(f, "[{name} \"{value}\"]")
+}
+
+fn parse_tag(tag: &str) -> Result<(&str, String), Box<dyn Error + Send + Sync>> {
+    let tag = tag.strip_suffix(']').ok_or("unclosed tag")?;
+    let (name, value) = tag.split_once(' ').ok_or("tag without value")?;
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or("tag value must be quoted")?;
+
+    let mut res = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => res.push(chars.next().ok_or("unfinished escape")?),
+            c => res.push(c),
+        }
+    }
+    Ok((name, res))
+}
+
+fn parse_cards(value: &str) -> Result<[usize; 5], Box<dyn Error + Send + Sync>> {
+    let cards = value
+        .split(',')
+        .map(|c| cards::find_card(c).ok_or("unknown card name"))
+        .collect::<Result<Vec<_>, _>>()?;
+    if (1..cards.len()).any(|i| cards[..i].contains(&cards[i])) {
+        return Err("duplicate card".into());
+    }
+    Ok(cards.try_into().map_err(|_| "expected 5 cards")?)
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_tag(f, "Blue", &self.names.blue)?;
+        write_tag(f, "Red", &self.names.red)?;
+        let cards: Vec<_> = self.cards.iter().map(|&c| cards::card(c).0).collect();
+        write_tag(f, "Cards", &cards.join(","))?;
+        if let Some(board) = &self.board {
+            write_tag(f, "Board", board)?;
+        }
+        if let Some(started) = self.started {
+            write_tag(f, "Started", &started.to_string())?;
+        }
+        if self.variant != Variant::Standard {
+            write_tag(f, "Variant", &self.variant.to_string())?;
+        }
+        if self.rules != RuleSet::DEFAULT {
+            write_tag(f, "Rules", &self.rules.to_string())?;
+        }
+        let result = match self.result {
+            Some(GameResult::Win(Color::Blue)) => "blue",
+            Some(GameResult::Win(Color::Red)) => "red",
+            Some(GameResult::Draw) => "draw",
+            None => "none",
+        };
+        write_tag(f, "Result", result)?;
+
+        for (i, pair) in self.moves.chunks(2).enumerate() {
+            write!(f, "\n{}.", i + 1)?;
+            for m in pair {
+                write!(f, " {}", m.mv)?;
+                if let Some(time) = m.time {
+                    write!(f, " {{{time}}}")?;
+                }
+            }
+        }
+        writeln!(f)
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = Box<dyn Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut blue, mut red, mut cards) = (None, None, None);
+        let (mut board, mut started, mut result) = (None, None, None);
+        let (mut variant, mut rules) = (Variant::Standard, RuleSet::DEFAULT);
+        let mut moves: Vec<RecordMove> = Vec::new();
+
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(tag) = line.strip_prefix('[') {
+                let (name, value) = parse_tag(tag)?;
+                match name {
+                    "Blue" => blue = Some(value),
+                    "Red" => red = Some(value),
+                    "Cards" => cards = Some(parse_cards(&value)?),
+                    "Board" => {
+                        if value.len() != 25 || !value.chars().all(|c| ('0'..='5').contains(&c)) {
+                            return Err("invalid board".into());
+                        }
+                        board = Some(value);
+                    }
+                    "Started" => started = Some(value.parse()?),
+                    "Variant" => variant = value.parse()?,
+                    "Rules" => rules = value.parse()?,
+                    "Result" => result = parse_result(&value)?,
+                    _ => {}
+                }
+                continue;
+            }
+
+            for token in line.split_whitespace() {
+                if let Some(time) = token.strip_prefix('{') {
+                    let time = time.strip_suffix('}').ok_or("unclosed timestamp")?;
+                    let last = moves.last_mut().ok_or("timestamp without move")?;
+                    last.time = Some(time.parse()?);
+                } else if let Some(number) = token.strip_suffix('.') {
+                    number.parse::<usize>()?;
+                } else {
+                    moves.push(RecordMove {
+                        mv: token.parse()?,
+                        time: None,
+                    });
+                }
+            }
+        }
+
+        Ok(GameRecord {
+            names: Sides {
+                blue: blue.ok_or("missing Blue tag")?,
+                red: red.ok_or("missing Red tag")?,
+            },
+            cards: cards.ok_or("missing Cards tag")?,
+            board,
+            started,
+            variant,
+            rules,
+            moves,
+            result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORD: &str = r#"[Blue "alice"]
+[Red "bob \"b\""]
+[Cards "ox,boar,horse,elephant,crab"]
+[Board "0000000000000000020040000"]
+[Started "1700000000000"]
+[Variant "light_and_shadow:red"]
+[Rules "self_check=no;temple=c;first=blue;ply_limit=none"]
+[Result "blue"]
+
+1. ox:c4c5 {1700000004000}
+"#;
+
+    #[test]
+    fn round_trip() {
+        let record: GameRecord = RECORD.parse().unwrap();
+        assert_eq!(record.names.red, "bob \"b\"");
+        assert_eq!(
+            record.variant,
+            Variant::LightAndShadow { shadow: Color::Red }
+        );
+        assert_eq!(record.moves[0].time, Some(1700000004000));
+        assert_eq!(record.to_string(), RECORD);
+    }
+
+    #[test]
+    fn replay_uses_rules() {
+        let mut record: GameRecord = RECORD.parse().unwrap();
+        let outcome = record.replay().unwrap().outcome_with(&record.rules);
+        assert!(outcome.is_some_and(|o| !o.winner.is_red));
+
+        record.rules.temple = None;
+        let outcome = record.replay().unwrap().outcome_with(&record.rules);
+        assert_eq!(outcome, None);
+
+        // red moves first, so the first move is not blue's
+        record.rules = "first=red".parse().unwrap();
+        assert!(record.replay().is_err());
+    }
+
+    #[test]
+    fn replay_checks_self_check() {
+        let text = r#"[Blue "alice"]
+[Red "bob"]
+[Cards "ox,boar,horse,elephant,crab"]
+[Board "0020000000003000000000400"]
+[Result "none"]
+
+1. ox:c1c2
+"#;
+        // the blue king moves next to a red pawn
+        let mut record: GameRecord = text.parse().unwrap();
+        assert!(record.replay().is_err());
+        assert!(record.final_result().is_err());
+
+        record.rules = RuleSet::OFFICIAL;
+        assert!(record.replay().is_ok());
+
+        // blue does not see the pawn when it is a ninja
+        record.rules = RuleSet::DEFAULT;
+        record.variant = Variant::LightAndShadow { shadow: Color::Red };
+        assert!(record.replay().is_ok());
+    }
+
+    #[test]
+    fn draw() {
+        let text = r#"[Blue "alice"]
+[Red "bob"]
+[Cards "ox,boar,horse,elephant,crab"]
+[Rules "self_check=no;temple=c;first=side;ply_limit=2"]
+[Result "draw"]
+
+1. ox:a1a2 horse:a5a4
+"#;
+        let mut record: GameRecord = text.parse().unwrap();
+        assert_eq!(record.result, Some(GameResult::Draw));
+        assert_eq!(record.to_string(), text);
+        assert_eq!(record.final_result().unwrap(), Some(GameResult::Draw));
+
+        let mv = "boar:b1b2".parse().unwrap();
+        record.moves.push(RecordMove { mv, time: None });
+        assert!(record.final_result().is_err());
+
+        record.moves.truncate(1);
+        assert_eq!(record.final_result().unwrap(), None);
+    }
+
+    #[test]
+    fn invalid() {
+        let bad = [
+            RECORD.replace("ox,", "ox,ox,"),
+            RECORD.replace("[Result \"blue\"]", "[Result \"green\"]"),
+            RECORD.replace("[Blue \"alice\"]", ""),
+            RECORD.replace("{1700000004000}", "{soon}"),
+        ];
+        for text in bad {
+            assert!(text.parse::<GameRecord>().is_err(), "{}", text);
+        }
+    }
+}
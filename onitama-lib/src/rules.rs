@@ -0,0 +1,125 @@
+//! Rules that differ between the official game and house variants.
+
+use std::{error::Error, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cards, state::PlayerColor};
+
+/// Rules that are chosen once per match.
+///
+/// Written as `self_check=yes;temple=c;first=side;ply_limit=200`, missing keys keep their default.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleSet {
+    // allow moves that leave your own king attacked, the official rules do
+    pub self_check: bool,
+    // column of the temple on the back row, counted from the left of its owner
+    // `None` turns off winning by the way of the stream
+    pub temple: Option<u8>,
+    pub first_player: FirstPlayer,
+    // the game is a draw after this many moves without a winner
+    #[serde(default)]
+    pub ply_limit: Option<u32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FirstPlayer {
+    // the color of the side card decides
+    SideCard,
+    Blue,
+    Red,
+}
+
+impl RuleSet {
+    // the rules that the client always used, moving into check is not allowed
+    pub const DEFAULT: Self = RuleSet {
+        self_check: false,
+        temple: Some(2),
+        first_player: FirstPlayer::SideCard,
+        ply_limit: None,
+    };
+    pub const OFFICIAL: Self = RuleSet {
+        self_check: true,
+        ..Self::DEFAULT
+    };
+
+    // temple of the active player, the waiting player wins by moving their king here
+    pub fn temple(&self) -> Option<usize> {
+        self.temple.map(|col| 20 + col as usize)
+    }
+
+    // temple of the waiting player, this is `temple` rotated 180 degrees
+    pub fn opp_temple(&self) -> Option<usize> {
+        self.temple.map(|col| 4 - col as usize)
+    }
+
+    pub fn red_starts(&self, side_card: usize) -> bool {
+        match self.first_player {
+            FirstPlayer::SideCard => cards::card(side_card).2 == PlayerColor::RED,
+            FirstPlayer::Blue => false,
+            FirstPlayer::Red => true,
+        }
+    }
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl fmt::Display for RuleSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let self_check = ["no", "yes"][self.self_check as usize];
+        let temple = match self.temple {
+            Some(col) => ((b'a' + col) as char).to_string(),
+            None => "none".to_owned(),
+        };
+        let first = match self.first_player {
+            FirstPlayer::SideCard => "side",
+            FirstPlayer::Blue => "blue",
+            FirstPlayer::Red => "red",
+        };
+        let ply_limit = match self.ply_limit {
+            Some(plies) => plies.to_string(),
+            None => "none".to_owned(),
+        };
+        write!(
+            f,
+            "self_check={self_check};temple={temple};first={first};ply_limit={ply_limit}"
+        )
+    }
+}
+
+impl FromStr for RuleSet {
+    type Err = Box<dyn Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => return Ok(Self::DEFAULT),
+            "official" => return Ok(Self::OFFICIAL),
+            _ => {}
+        }
+        let mut res = Self::DEFAULT;
+        for part in s.split(';') {
+            let (key, value) = part.split_once('=').ok_or("expected key=value")?;
+            match (key, value) {
+                ("self_check", "yes") => res.self_check = true,
+                ("self_check", "no") => res.self_check = false,
+                ("temple", "none") => res.temple = None,
+                ("temple", col) if col.len() == 1 && ("a"..="e").contains(&col) => {
+                    res.temple = Some(col.as_bytes()[0] - b'a')
+                }
+                ("first", "side") => res.first_player = FirstPlayer::SideCard,
+                ("first", "blue") => res.first_player = FirstPlayer::Blue,
+                ("first", "red") => res.first_player = FirstPlayer::Red,
+                ("ply_limit", "none") => res.ply_limit = None,
+                ("ply_limit", plies) => res.ply_limit = Some(plies.parse()?),
+                _ => return Err("unknown rule".into()),
+            }
+        }
+        Ok(res)
+    }
+}
@@ -8,9 +8,11 @@ use std::{
     str::FromStr,
 };
 
-use crate::{get_offset, in_card, Cards, PieceKind, Sides, CARDS};
+use crate::{
+    cards::card, get_offset, in_card, rules::RuleSet, Cards, Move, MoveError, PieceKind, Sides,
+};
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Piece<Player = PlayerTurn>(pub Player, pub PieceKind);
 
 impl Piece {
@@ -20,6 +22,7 @@ impl Piece {
     pub const WAITING_KING: Self = Self(PlayerTurn::WAITING, PieceKind::King);
 }
 
+#[derive(Clone)]
 pub struct State<Pos = Perspective, Player = PlayerTurn> {
     pub board: [Option<Piece<Player>>; 25],
     pub table_card: usize,
@@ -33,17 +36,46 @@ impl State<NamedField, PlayerColor> {
         Cards {
             players: Sides {
                 blue: vec![
-                    CARDS[self.cards[&PlayerColor::BLUE][0]].0.to_owned(),
-                    CARDS[self.cards[&PlayerColor::BLUE][1]].0.to_owned(),
+                    card(self.cards[&PlayerColor::BLUE][0]).0.to_owned(),
+                    card(self.cards[&PlayerColor::BLUE][1]).0.to_owned(),
                 ],
                 red: vec![
-                    CARDS[self.cards[&PlayerColor::RED][0]].0.to_owned(),
-                    CARDS[self.cards[&PlayerColor::RED][1]].0.to_owned(),
+                    card(self.cards[&PlayerColor::RED][0]).0.to_owned(),
+                    card(self.cards[&PlayerColor::RED][1]).0.to_owned(),
                 ],
             },
-            side: CARDS[self.table_card].0.to_owned(),
+            side: card(self.table_card).0.to_owned(),
         }
     }
+
+    pub fn outcome(&self) -> Option<Outcome<PlayerColor>> {
+        self.outcome_with(&RuleSet::DEFAULT)
+    }
+
+    pub fn outcome_with(&self, rules: &RuleSet) -> Option<Outcome<PlayerColor>> {
+        let state: State = self.clone().translate();
+        let outcome = state.outcome_with(rules)?;
+        Some(Outcome {
+            winner: outcome.winner.translate(self.active_eq_red),
+            way: outcome.way,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Way {
+    // capture the king of the opponent
+    Stone,
+    // move your king onto the temple of the opponent
+    Stream,
+    // leave the opponent in check without a legal move, this only happens without self check
+    Mate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome<Player = PlayerTurn> {
+    pub winner: Player,
+    pub way: Way,
 }
 
 // impl Default for State {
@@ -59,49 +91,252 @@ impl State<NamedField, PlayerColor> {
 // }
 
 impl State {
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome_with(&RuleSet::DEFAULT)
+    }
+
+    // same as `outcome`, but the temple squares and the legal moves come from `rules`
+    pub fn outcome_with(&self, rules: &RuleSet) -> Option<Outcome> {
+        if let Some(outcome) = self.board_outcome(rules) {
+            return Some(outcome);
+        }
+        let stuck = crate::legal_moves_with(self, rules).next().is_none();
+        stuck.then_some(Outcome {
+            winner: PlayerTurn::WAITING,
+            way: Way::Mate,
+        })
+    }
+
+    // a captured king or a king on a temple
+    pub(crate) fn board_outcome(&self, rules: &RuleSet) -> Option<Outcome> {
+        let has_king = |player| self.board.contains(&Some(Piece(player, PieceKind::King)));
+        let (winner, way) = if !has_king(PlayerTurn::ACTIVE) {
+            (PlayerTurn::WAITING, Way::Stone)
+        } else if !has_king(PlayerTurn::WAITING) {
+            (PlayerTurn::ACTIVE, Way::Stone)
+        } else if rules
+            .temple()
+            .is_some_and(|t| self.board[t] == Some(Piece::WAITING_KING))
+        {
+            (PlayerTurn::WAITING, Way::Stream)
+        } else if rules
+            .opp_temple()
+            .is_some_and(|t| self.board[t] == Some(Piece::ACTIVE_KING))
+        {
+            (PlayerTurn::ACTIVE, Way::Stream)
+        } else {
+            return None;
+        };
+        Some(Outcome { winner, way })
+    }
+
+    // moves into check and passes are checked with `rules`
     pub fn make_move<X: Translate<Perspective>>(
+        self,
+        card: &str,
+        from: X,
+        to: X,
+        rules: &RuleSet,
+    ) -> Result<Self, MoveError> {
+        self.make_spirit_move(card, from, to, None, rules)
+    }
+
+    // same as `make_move`, `spirit` is where the wind spirit goes for cards with spirit moves
+    pub fn make_spirit_move<X: Translate<Perspective>>(
         mut self,
         card: &str,
         from: X,
         to: X,
-    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
-        let from = from.translate(self.active_eq_red);
-        let from = Perspective::range().position(|x| x == from).unwrap();
-        let to = to.translate(self.active_eq_red);
-        let to = Perspective::range().position(|x| x == to).unwrap();
-        if !self.board[from].is_some_and(|x| x.0 == PlayerTurn::ACTIVE) {
-            return Err("can only move your own pieces".into());
+        spirit: Option<X>,
+        rules: &RuleSet,
+    ) -> Result<Self, MoveError> {
+        let from = from.translate(self.active_eq_red).index();
+        let to = to.translate(self.active_eq_red).index();
+        let spirit = spirit.map(|x| x.translate(self.active_eq_red).index());
+        if from == to {
+            if spirit.is_some() {
+                return Err(MoveError::SpiritWhilePassing);
+            }
+            return self.make_pass(card, rules);
         }
-        if self.board[to].is_some_and(|x| x.0 == PlayerTurn::ACTIVE) {
-            return Err("can not move onto your own piece".into());
+        let is_spirit = |piece: Option<Piece>| piece.is_some_and(|x| x.1 == PieceKind::Spirit);
+        let is_king = |piece: Option<Piece>| piece.is_some_and(|x| x.1 == PieceKind::King);
+
+        let moves_spirit = is_spirit(self.board[from]);
+        if moves_spirit {
+            if is_king(self.board[to]) {
+                return Err(MoveError::SpiritOntoKing);
+            }
+        } else {
+            if !self.board[from].is_some_and(|x| x.0 == PlayerTurn::ACTIVE) {
+                return Err(MoveError::NotYourPiece);
+            }
+            if is_spirit(self.board[to]) {
+                return Err(MoveError::CaptureSpirit);
+            }
+            if self.board[to].is_some_and(|x| x.0 == PlayerTurn::ACTIVE) {
+                return Err(MoveError::OntoOwnPiece);
+            }
         }
-        self.board[to] = take(&mut self.board[from]);
-        let card = CARDS
-            .iter()
-            .position(|x| x.0 == card)
-            .ok_or("unknown card name")?;
-        let offset = get_offset(to, from).ok_or("move too far")?;
+        let card = crate::cards::find_card(card).ok_or(MoveError::UnknownCard)?;
+        let offset = get_offset(to, from).ok_or(MoveError::TooFar)?;
         if !in_card(offset, card) {
-            return Err("invalid move for card".into());
+            return Err(MoveError::InvalidForCard);
+        }
+        if !self.cards[&PlayerTurn::ACTIVE].contains(&card) {
+            return Err(MoveError::CardNotInHand);
+        }
+
+        let spirit_offsets = crate::cards::spirit_moves(card);
+        match spirit {
+            None if !spirit_offsets.is_empty() => {
+                return Err(MoveError::SpiritMoveMissing);
+            }
+            Some(_) if spirit_offsets.is_empty() || moves_spirit => {
+                return Err(MoveError::SpiritMoveNotAllowed);
+            }
+            Some(target) => {
+                let pos = crate::wind::spirit(&self).ok_or(MoveError::NoSpirit)?;
+                let offset = get_offset(target, pos).ok_or(MoveError::SpiritTooFar)?;
+                if !spirit_offsets.contains(&offset) {
+                    return Err(MoveError::InvalidSpiritMove);
+                }
+                // the piece has already moved when the spirit moves
+                let piece = match target {
+                    t if t == to => self.board[from],
+                    t if t == from => None,
+                    t => self.board[t],
+                };
+                if is_king(piece) {
+                    return Err(MoveError::SpiritOntoKing);
+                }
+            }
+            None => {}
+        }
+
+        let m = Move {
+            card,
+            from,
+            to,
+            spirit,
+        };
+        if !rules.self_check {
+            let mut next = self.clone();
+            next.move_pieces(m);
+            if crate::is_check(&next) {
+                return Err(MoveError::SelfCheck);
+            }
+        }
+        self.apply(m);
+        Ok(self)
+    }
+
+    // pass the card to the opponent without moving, only allowed when no piece has a move that `rules` allow
+    pub fn make_pass(mut self, card: &str, rules: &RuleSet) -> Result<Self, MoveError> {
+        let card = crate::cards::find_card(card).ok_or(MoveError::UnknownCard)?;
+        if !self.cards[&PlayerTurn::ACTIVE].contains(&card) {
+            return Err(MoveError::CardNotInHand);
         }
+        if !crate::can_pass_with(&self, rules) {
+            return Err(MoveError::CanNotPass);
+        }
+        self.apply(Move::pass(card));
+        Ok(self)
+    }
+
+    // make a move without validating it, the returned `Undo` can be used to take it back
+    pub fn apply(&mut self, m: Move) -> Undo {
+        let captured = self.board[m.to].filter(|_| !m.is_pass()).map(|x| x.1);
+        let undo = Undo {
+            mv: m,
+            captured,
+            spirit: self.move_pieces(m),
+            table_card: self.table_card,
+        };
         let have = self
             .cards
             .get_mut(&PlayerTurn::ACTIVE)
             .unwrap()
             .iter_mut()
-            .find(|x| **x == card)
-            .ok_or("you do not have that card")?;
+            .find(|x| **x == m.card)
+            .expect("the active player does not have the card");
         swap(have, &mut self.table_card);
+        self.flip();
+        undo
+    }
 
-        // go to global perspective to easily switch active player
-        let mut s: State<NamedField, PlayerColor> = self.translate();
-        s.active_eq_red ^= true;
+    // take back the last move made with `apply`
+    pub fn unapply(&mut self, undo: Undo) {
+        self.flip();
+        let m = undo.mv;
+        let have = self
+            .cards
+            .get_mut(&PlayerTurn::ACTIVE)
+            .unwrap()
+            .iter_mut()
+            .find(|x| **x == undo.table_card)
+            .expect("the undo does not belong to this state");
+        swap(have, &mut self.table_card);
 
-        Ok(s.translate())
+        if m.is_pass() {
+            return;
+        }
+        if undo.spirit == Some(m.from) {
+            self.board.swap(m.from, m.to);
+            return;
+        }
+        if let (Some(spirit), Some(target)) = (undo.spirit, m.spirit) {
+            self.board.swap(spirit, target);
+        }
+        self.board[m.from] = take(&mut self.board[m.to]);
+        self.board[m.to] = undo.captured.map(|kind| Piece(PlayerTurn::WAITING, kind));
+    }
+
+    // move the pieces without changing the cards or the active player
+    // the wind spirit swaps places with pawns instead of capturing them
+    // returns where the wind spirit was if it moved
+    pub(crate) fn move_pieces(&mut self, m: Move) -> Option<usize> {
+        if m.is_pass() {
+            return None;
+        }
+        if self.board[m.from].is_some_and(|x| x.1 == PieceKind::Spirit) {
+            self.board.swap(m.from, m.to);
+            return Some(m.from);
+        }
+        self.board[m.to] = take(&mut self.board[m.from]);
+        let target = m.spirit?;
+        let spirit = crate::wind::spirit(self).expect("there is no wind spirit");
+        self.board.swap(spirit, target);
+        Some(spirit)
+    }
+
+    // make the waiting player the active player
+    // rotating the board 180 degrees is the same as reversing the indices
+    pub fn flip(&mut self) {
+        self.board.reverse();
+        for piece in self.board.iter_mut().flatten() {
+            piece.0.is_active ^= true;
+        }
+        let active = self.cards[&PlayerTurn::ACTIVE];
+        let waiting = self.cards.insert(PlayerTurn::WAITING, active).unwrap();
+        self.cards.insert(PlayerTurn::ACTIVE, waiting);
+        self.active_eq_red ^= true;
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+// everything needed to take back a move made with `State::apply`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+    pub mv: Move,
+    // the piece that was on the target square
+    pub captured: Option<PieceKind>,
+    // where the wind spirit was, if it moved
+    pub spirit: Option<usize>,
+    // the table card before the move
+    pub table_card: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct PlayerTurn {
     pub is_active: bool,
 }
@@ -111,7 +346,7 @@ impl PlayerTurn {
     pub const WAITING: Self = Self { is_active: false };
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct PlayerColor {
     // blue starting row is 1, a to e is left to right for blue
     // blue is the starting player?
@@ -123,7 +358,7 @@ impl PlayerColor {
     pub const BLUE: Self = Self { is_red: false };
 }
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct NamedField {
     pub col: char, // one of a, b, c, d, e
     pub row: char, // one of 1, 2, 3, 4, 5
@@ -159,12 +394,26 @@ impl PosRange for NamedField {
     }
 }
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
 pub struct Perspective {
     pub col: u8, // left to right for active player
     pub row: u8, // back to front for active player
 }
 
+impl Perspective {
+    // index into `State::board`, same as the position in `Perspective::range()`
+    pub fn index(self) -> usize {
+        self.row as usize * 5 + self.col as usize
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        Self {
+            col: (index % 5) as u8,
+            row: (index / 5) as u8,
+        }
+    }
+}
+
 impl PosRange for Perspective {
     fn range() -> impl Iterator<Item = Self> {
         (0..5).flat_map(|row| (0..5).map(move |col| Self { col, row }))
@@ -192,8 +441,8 @@ impl Translate<NamedField> for Perspective {
             self.row = 4 - self.row;
         }
         NamedField {
-            col: ('a' as u8 + self.col) as char,
-            row: ('5' as u8 - self.row) as char,
+            col: (b'a' + self.col) as char,
+            row: (b'5' - self.row) as char,
         }
     }
 }
@@ -201,8 +450,8 @@ impl Translate<NamedField> for Perspective {
 impl Translate<Perspective> for NamedField {
     fn translate(self, active_eq_red: bool) -> Perspective {
         let mut res = Perspective {
-            col: self.col as u8 - 'a' as u8,
-            row: '5' as u8 - self.row as u8,
+            col: self.col as u8 - b'a',
+            row: b'5' - self.row as u8,
         };
         if active_eq_red {
             res.col = 4 - res.col;
@@ -0,0 +1,159 @@
+//! Symmetries that do not change the value of a position.
+//!
+//! [`BitState`] is always seen by the active player, so swapping the colors together with a
+//! 180 degree rotation only changes `active_eq_red`. Mirroring left and right also mirrors
+//! the moves on every card, so it only works when the mirrored cards exist. Both assume that
+//! the temples are in the middle, like in the official rules.
+//!
+//! Only the opening book uses [`BitState::canonical_key`]. The transposition table keeps the
+//! plain keys, because they are updated with every move and the engine also searches with
+//! other temples. The tablebase index counts every position, mirrored ones included.
+
+use crate::{
+    bitboard::{BitState, ACTIVE, WAITING},
+    cards, Move,
+};
+
+pub fn mirror_square(pos: usize) -> usize {
+    pos / 5 * 5 + 4 - pos % 5
+}
+
+pub fn mirror_mask(mask: u32) -> u32 {
+    (0..25)
+        .filter(|pos| mask & 1 << pos != 0)
+        .fold(0, |acc, pos| acc | 1 << mirror_square(pos))
+}
+
+fn sorted(offsets: impl IntoIterator<Item = usize>) -> Vec<usize> {
+    let mut res: Vec<_> = offsets.into_iter().collect();
+    res.sort();
+    res
+}
+
+// card offsets use the same 5x5 grid as the board
+fn is_mirror(a: usize, b: usize) -> bool {
+    let mirrored = |offsets: &[usize]| sorted(offsets.iter().map(|&o| mirror_square(o)));
+    mirrored(cards::card(a).1) == sorted(cards::card(b).1.iter().copied())
+        && mirrored(cards::spirit_moves(a)) == sorted(cards::spirit_moves(b).iter().copied())
+}
+
+// the card with mirrored moves, cards that are symmetric are their own mirror
+// there is no mirror when several cards qualify in a way that can not be undone
+pub fn mirror_card(card: usize) -> Option<usize> {
+    let find = |card| match is_mirror(card, card) {
+        true => Some(card),
+        false => (0..cards::card_count()).find(|&other| is_mirror(card, other)),
+    };
+    find(card).filter(|&mirror| find(mirror) == Some(card))
+}
+
+pub fn mirror_move(m: Move) -> Option<Move> {
+    let card = mirror_card(m.card)?;
+    if m.is_pass() {
+        return Some(Move::pass(card));
+    }
+    Some(Move {
+        card,
+        from: mirror_square(m.from),
+        to: mirror_square(m.to),
+        spirit: m.spirit.map(mirror_square),
+    })
+}
+
+impl BitState {
+    // the same position mirrored left and right
+    pub fn mirror(&self) -> Option<BitState> {
+        let card = |c: u8| mirror_card(c as usize).map(|c| c as u8);
+        Some(BitState {
+            pawns: self.pawns.map(mirror_mask),
+            kings: self.kings.map(mirror_mask),
+            cards: [
+                [card(self.cards[ACTIVE][0])?, card(self.cards[ACTIVE][1])?],
+                [card(self.cards[WAITING][0])?, card(self.cards[WAITING][1])?],
+            ],
+            table_card: card(self.table_card)?,
+            active_eq_red: self.active_eq_red,
+        })
+    }
+
+    /// One position for every group of positions that are the same up to symmetry.
+    ///
+    /// The colors are always the same and the cards in each hand are sorted.
+    /// Also returns whether the position was mirrored, moves of the canonical
+    /// position have to be mirrored with [`mirror_move`] to be played here.
+    pub fn canonical(&self) -> (BitState, bool) {
+        let normal = |mut state: BitState| {
+            state.active_eq_red = false;
+            state.cards[ACTIVE].sort();
+            state.cards[WAITING].sort();
+            state
+        };
+        let state = normal(*self);
+        match self.mirror().map(normal) {
+            Some(mirror) if mirror.key() < state.key() => (mirror, true),
+            _ => (state, false),
+        }
+    }
+
+    // the same for all symmetric positions, see `canonical`
+    pub fn canonical_key(&self) -> u64 {
+        self.canonical().0.key()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::{
+        cards::{find_card, register_card, CardDef},
+        state::State,
+        Color,
+    };
+
+    fn card(name: &str) -> usize {
+        find_card(name).unwrap()
+    }
+
+    #[test]
+    fn mirror_cards() {
+        assert_eq!(mirror_card(card("ox")), Some(card("horse")));
+        assert_eq!(mirror_card(card("horse")), Some(card("ox")));
+        assert_eq!(mirror_card(card("frog")), Some(card("rabbit")));
+        assert_eq!(mirror_card(card("crab")), Some(card("crab")));
+
+        let lopsided = CardDef {
+            name: "test_lopsided".to_owned(),
+            moves: vec![(1, 2)],
+            spirit_moves: Vec::new(),
+            color: Color::Blue,
+        };
+        assert_eq!(mirror_card(register_card(&lopsided).unwrap()), None);
+    }
+
+    #[test]
+    fn canonical_positions() {
+        let start = BitState::try_from(&State::new_game([0, 1, 2, 3, 4])).unwrap();
+        for m in start.legal_moves() {
+            let next = start.play(m);
+            let mirrored = start.mirror().unwrap().play(mirror_move(m).unwrap());
+            assert_eq!(mirrored, next.mirror().unwrap());
+            assert_eq!(mirrored.canonical_key(), next.canonical_key());
+
+            let mut swapped = next;
+            swapped.active_eq_red ^= true;
+            assert_eq!(swapped.canonical_key(), next.canonical_key());
+        }
+
+        // moves of the canonical position are mirrored back
+        let (canonical, mirrored) = start.canonical();
+        for m in canonical.legal_moves() {
+            let m = match mirrored {
+                true => mirror_move(m).unwrap(),
+                false => m,
+            };
+            assert!(start.legal_moves().any(|x| x == m));
+        }
+    }
+}
@@ -0,0 +1,465 @@
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    error::Error,
+    io::{self, Read, Write},
+};
+
+use crate::{
+    bitboard::{card_targets, BitState, ACTIVE, WAITING},
+    cards,
+    state::State,
+    Move,
+};
+
+// the version was bumped when passing was added to the rules
+const MAGIC: &[u8; 4] = b"OTB2";
+
+// every extra pawn multiplies the size by about ten, generating four pawns takes 9 GB of memory
+pub const MAX_PAWNS: u32 = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Value {
+    // the active player wins in this many moves
+    Win(u8),
+    // the active player loses in this many moves
+    Loss(u8),
+    Draw,
+}
+
+impl Value {
+    // 0 is a draw, 1..=127 is a win and 128.. is a loss, longer distances do not fit
+    fn to_byte(self) -> Option<u8> {
+        match self {
+            Value::Draw => Some(0),
+            Value::Win(d) => (1..128).contains(&d).then_some(d),
+            Value::Loss(d) => (d < 128).then_some(128 + d),
+        }
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 => Value::Draw,
+            1..=127 => Value::Win(byte),
+            _ => Value::Loss(byte - 128),
+        }
+    }
+
+    // the value for the player that made the move leading to a position with this value
+    pub fn before(self) -> Self {
+        match self {
+            Value::Win(d) => Value::Loss(d + 1),
+            Value::Loss(d) => Value::Win(d + 1),
+            Value::Draw => Value::Draw,
+        }
+    }
+}
+
+const fn binomials() -> [[usize; 26]; 26] {
+    let mut res = [[0; 26]; 26];
+    let mut n = 0;
+    while n < 26 {
+        res[n][0] = 1;
+        let mut k = 1;
+        while k <= n {
+            res[n][k] = res[n - 1][k - 1] + res[n - 1][k];
+            k += 1;
+        }
+        n += 1;
+    }
+    res
+}
+
+static BINOMIAL: [[usize; 26]; 26] = binomials();
+
+// number of ways to place the pawns of both players, ignoring overlaps
+fn placements(active: usize, waiting: usize) -> usize {
+    BINOMIAL[25][active] * BINOMIAL[25][waiting]
+}
+
+// index of a set of squares among all sets with the same number of squares
+fn rank(mask: u32) -> usize {
+    (0..25)
+        .filter(|pos| mask & 1 << pos != 0)
+        .enumerate()
+        .map(|(i, pos)| BINOMIAL[pos][i + 1])
+        .sum()
+}
+
+fn unrank(mut rank: usize, count: usize) -> u32 {
+    let mut mask = 0;
+    for k in (1..=count).rev() {
+        let pos = (k - 1..25)
+            .rev()
+            .find(|&pos| BINOMIAL[pos][k] <= rank)
+            .unwrap();
+        rank -= BINOMIAL[pos][k];
+        mask |= 1 << pos;
+    }
+    mask
+}
+
+/// Win/loss/draw values for every position with both kings and at most
+/// `max_pawns` pawns, using one fixed set of five cards.
+///
+/// The rules are the same as `BitState::make_move` with the official rules, a player without piece moves has to pass.
+pub struct Tablebase {
+    // sorted card indices
+    cards: [usize; 5],
+    max_pawns: u32,
+    // start of the positions with (active pawns, waiting pawns), indexed by `active * (max_pawns + 1) + waiting`
+    offsets: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl Tablebase {
+    fn empty(mut cards: [usize; 5], max_pawns: u32) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if max_pawns > MAX_PAWNS {
+            return Err(format!("tablebases have at most {MAX_PAWNS} pawns").into());
+        }
+        if cards.iter().any(|&c| !cards::spirit_moves(c).is_empty()) {
+            return Err("tablebases do not support the wind spirit".into());
+        }
+        cards.sort_unstable();
+        let mut offsets = Vec::new();
+        let mut len = 0;
+        for active in 0..=max_pawns as usize {
+            for waiting in 0..=max_pawns as usize {
+                offsets.push(len);
+                if active + waiting <= max_pawns as usize {
+                    len += 30 * 25 * 25 * placements(active, waiting);
+                }
+            }
+        }
+        Ok(Tablebase {
+            cards,
+            max_pawns,
+            offsets,
+            data: vec![0; len],
+        })
+    }
+
+    fn card_bit(&self, card: u8) -> Option<usize> {
+        self.cards.iter().position(|&c| c == card as usize)
+    }
+
+    fn index(&self, state: &BitState) -> Option<usize> {
+        let (active, waiting) = (
+            state.pawns[ACTIVE].count_ones() as usize,
+            state.pawns[WAITING].count_ones() as usize,
+        );
+        if active + waiting > self.max_pawns as usize
+            || state.kings[ACTIVE] == 0
+            || state.kings[WAITING] == 0
+        {
+            return None;
+        }
+
+        // cards: which pair the active player has and which of the other three is on the table
+        let (a, b) = (
+            self.card_bit(state.cards[ACTIVE][0])?,
+            self.card_bit(state.cards[ACTIVE][1])?,
+        );
+        let (a, b) = (a.min(b), a.max(b));
+        let table = self.card_bit(state.table_card)?;
+        for card in state.cards[WAITING] {
+            self.card_bit(card)?;
+        }
+        let table_idx = table - (a < table) as usize - (b < table) as usize;
+        let cards = (a + b * (b - 1) / 2) * 3 + table_idx;
+
+        let kings = state.kings[ACTIVE].trailing_zeros() as usize * 25
+            + state.kings[WAITING].trailing_zeros() as usize;
+        let pawns = rank(state.pawns[ACTIVE]) * BINOMIAL[25][waiting] + rank(state.pawns[WAITING]);
+        let per_kings = placements(active, waiting);
+        let offset = self.offsets[active * (self.max_pawns as usize + 1) + waiting];
+        Some(offset + (cards * 625 + kings) * per_kings + pawns)
+    }
+
+    // the position at `index`, or `None` if pieces overlap
+    fn decode(&self, index: usize) -> Option<BitState> {
+        // empty signatures share their offset with the next one
+        let sig = self.offsets.iter().rposition(|&o| o <= index).unwrap();
+        let (active, waiting) = (
+            sig / (self.max_pawns as usize + 1),
+            sig % (self.max_pawns as usize + 1),
+        );
+        let per_kings = placements(active, waiting);
+        let index = index - self.offsets[sig];
+        let (rest, pawns) = (index / per_kings, index % per_kings);
+        let (cards, kings) = (rest / 625, rest % 625);
+
+        let (pair, table_idx) = (cards / 3, cards % 3);
+        let b = (1..5).find(|&b| pair < b * (b + 1) / 2).unwrap();
+        let a = pair - b * (b - 1) / 2;
+        let table = (0..5).filter(|&c| c != a && c != b).nth(table_idx).unwrap();
+        let mut other = (0..5).filter(|&c| c != a && c != b && c != table);
+
+        let state = BitState {
+            pawns: [
+                unrank(pawns / BINOMIAL[25][waiting], active),
+                unrank(pawns % BINOMIAL[25][waiting], waiting),
+            ],
+            kings: [1 << (kings / 25), 1 << (kings % 25)],
+            cards: [
+                [self.cards[a] as u8, self.cards[b] as u8],
+                [
+                    self.cards[other.next().unwrap()] as u8,
+                    self.cards[other.next().unwrap()] as u8,
+                ],
+            ],
+            table_card: self.cards[table] as u8,
+            active_eq_red: false,
+        };
+        let total = (state.pieces(ACTIVE) | state.pieces(WAITING)).count_ones();
+        (total as usize == active + waiting + 2).then_some(state)
+    }
+
+    // all positions with a move that leads to `state`
+    fn predecessors(&self, state: &BitState) -> Vec<BitState> {
+        // look from the perspective of the player that made the last move
+        let mut prev = *state;
+        prev.flip();
+
+        let card = prev.table_card as usize;
+        let occupied = prev.pieces(ACTIVE) | prev.pieces(WAITING);
+        let pawns = prev.pawns[ACTIVE].count_ones() + prev.pawns[WAITING].count_ones();
+        let mut res = Vec::new();
+        for slot in 0..2 {
+            // a pass does not change the board, it was only allowed when no piece could move
+            let mut before = prev;
+            before.table_card = prev.cards[ACTIVE][slot];
+            before.cards[ACTIVE][slot] = card as u8;
+            if !before.is_lost() && before.piece_moves().next().is_none() {
+                res.push(before);
+            }
+
+            for to in (0..25).filter(|to| prev.pieces(ACTIVE) & 1 << to != 0) {
+                for from in (0..25).filter(|from| occupied & 1 << from == 0) {
+                    if card_targets(card, from) & 1 << to == 0 {
+                        continue;
+                    }
+                    let mut before = prev;
+                    before.table_card = prev.cards[ACTIVE][slot];
+                    before.cards[ACTIVE][slot] = card as u8;
+                    if before.pawns[ACTIVE] & 1 << to != 0 {
+                        before.pawns[ACTIVE] ^= 1 << from | 1 << to;
+                    } else {
+                        before.kings[ACTIVE] ^= 1 << from | 1 << to;
+                    }
+                    if before.is_lost() {
+                        continue;
+                    }
+                    res.push(before);
+                    if pawns < self.max_pawns {
+                        before.pawns[WAITING] |= 1 << to;
+                        res.push(before);
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    // fails when there are too many pawns, or when a win takes too many moves to store
+    pub fn generate(
+        cards: [usize; 5],
+        max_pawns: u32,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut tb = Tablebase::empty(cards, max_pawns)?;
+        let mut remaining = vec![0u8; tb.data.len()];
+
+        // positions that are decided right away, losses first so that the queue stays sorted by distance
+        let mut losses = VecDeque::new();
+        let mut wins = VecDeque::new();
+        for (index, remaining) in remaining.iter_mut().enumerate() {
+            let Some(state) = tb.decode(index) else {
+                continue;
+            };
+            if state.is_lost() {
+                // nothing moves into these positions without winning right away, so they are not queued
+                tb.data[index] = Value::Loss(0).to_byte().unwrap();
+                continue;
+            }
+            let mut count = 0;
+            let mut wins_now = false;
+            for m in state.moves() {
+                count += 1;
+                wins_now |= state.play(m).is_lost();
+            }
+            if wins_now {
+                tb.data[index] = Value::Win(1).to_byte().unwrap();
+                wins.push_back(index);
+            } else if count == 0 {
+                tb.data[index] = Value::Loss(0).to_byte().unwrap();
+                losses.push_back(index);
+            } else {
+                *remaining = count;
+            }
+        }
+
+        let mut queue = losses;
+        queue.append(&mut wins);
+        while let Some(index) = queue.pop_front() {
+            let value = Value::from_byte(tb.data[index]);
+            let state = tb.decode(index).unwrap();
+            for prev in tb.predecessors(&state) {
+                let prev_index = tb.index(&prev).unwrap();
+                if tb.data[prev_index] != 0 {
+                    continue;
+                }
+                match value {
+                    Value::Loss(_) => {}
+                    Value::Win(_) => {
+                        remaining[prev_index] -= 1;
+                        if remaining[prev_index] != 0 {
+                            continue;
+                        }
+                    }
+                    Value::Draw => unreachable!(),
+                }
+                tb.data[prev_index] = value
+                    .before()
+                    .to_byte()
+                    .ok_or("a win takes too many moves for the tablebase format")?;
+                queue.push_back(prev_index);
+            }
+        }
+        Ok(tb)
+    }
+
+    pub fn cards(&self) -> [usize; 5] {
+        self.cards
+    }
+
+    pub fn max_pawns(&self) -> u32 {
+        self.max_pawns
+    }
+
+    // number of (wins, losses, draws) and the longest win, positions where the game is over are not counted
+    pub fn stats(&self) -> (usize, usize, usize, u8) {
+        let (mut wins, mut losses, mut draws, mut longest) = (0, 0, 0, 0);
+        for index in 0..self.data.len() {
+            match self.decode(index) {
+                Some(state) if !state.is_lost() => {}
+                _ => continue,
+            }
+            match Value::from_byte(self.data[index]) {
+                Value::Win(d) => {
+                    wins += 1;
+                    longest = longest.max(d);
+                }
+                Value::Loss(_) => losses += 1,
+                Value::Draw => draws += 1,
+            }
+        }
+        (wins, losses, draws, longest)
+    }
+
+    // `None` if the position is not in this tablebase
+    pub fn probe(&self, state: &BitState) -> Option<Value> {
+        let index = self.index(state)?;
+        Some(Value::from_byte(self.data[index]))
+    }
+
+    // positions with the wind spirit are never in a tablebase
+    pub fn probe_state(&self, state: &State) -> Option<Value> {
+        self.probe(&BitState::try_from(state).ok()?)
+    }
+
+    // a move that keeps the best value: the fastest win, a draw, or the slowest loss
+    pub fn best_move(&self, state: &BitState) -> Option<(Move, Value)> {
+        self.probe(state)?;
+        state
+            .moves()
+            .map(|m| {
+                let next = state.play(m);
+                let value = match next.is_lost() {
+                    true => Value::Loss(0),
+                    false => self.probe(&next).unwrap(),
+                };
+                (m, value.before())
+            })
+            .max_by_key(|(_, value)| match *value {
+                Value::Win(d) => 1000 - d as i32,
+                Value::Draw => 0,
+                Value::Loss(d) => -1000 + d as i32,
+            })
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[self.max_pawns as u8])?;
+        for card in self.cards {
+            let name = cards::card(card).0;
+            w.write_all(&[name.len() as u8])?;
+            w.write_all(name.as_bytes())?;
+        }
+        w.write_all(&self.data)
+    }
+
+    pub fn read(r: &mut impl Read) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err("not a tablebase file".into());
+        }
+        let mut byte = [0];
+        r.read_exact(&mut byte)?;
+        let max_pawns = byte[0] as u32;
+
+        let mut cards = [0; 5];
+        for card in &mut cards {
+            r.read_exact(&mut byte)?;
+            let mut name = vec![0; byte[0] as usize];
+            r.read_exact(&mut name)?;
+            *card = std::str::from_utf8(&name)
+                .ok()
+                .and_then(cards::find_card)
+                .ok_or("unknown card name")?;
+        }
+
+        let mut tb = Tablebase::empty(cards, max_pawns)?;
+        if tb.cards != cards {
+            return Err("cards are not sorted".into());
+        }
+        r.read_exact(&mut tb.data)?;
+        if r.read(&mut byte)? != 0 {
+            return Err("unexpected data after tablebase".into());
+        }
+        Ok(tb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_agree_with_moves() {
+        let tb = Tablebase::generate([0, 1, 2, 3, 4], 0).unwrap();
+        for index in 0..tb.data.len() {
+            match tb.decode(index) {
+                Some(state) if !state.is_lost() => {
+                    let (_, value) = tb.best_move(&state).unwrap();
+                    assert_eq!(tb.probe(&state), Some(value));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn write_read() {
+        let tb = Tablebase::generate([5, 1, 9, 3, 4], 0).unwrap();
+        let mut bytes = Vec::new();
+        tb.write(&mut bytes).unwrap();
+        let read = Tablebase::read(&mut &bytes[..]).unwrap();
+        assert_eq!((read.cards, read.data), (tb.cards, tb.data));
+    }
+
+    #[test]
+    fn too_many_pawns() {
+        assert!(Tablebase::generate([0, 1, 2, 3, 4], MAX_PAWNS + 1).is_err());
+    }
+}
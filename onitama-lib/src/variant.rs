@@ -0,0 +1,157 @@
+//! Variants where players do not see the whole board.
+//!
+//! In Light and Shadow the students of the shadow player are ninjas. Only the
+//! shadow player knows where they are, the light player and spectators see the
+//! masters and the light students. Pieces move and capture as usual, so the
+//! light player can capture a ninja by moving onto its square by chance.
+
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::{
+    notation::NamedMove,
+    rules::RuleSet,
+    state::{NamedField, Outcome, Perspective, Piece, PlayerColor, State, Translate},
+    Color, MoveError, PieceKind,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Variant {
+    Standard,
+    LightAndShadow { shadow: Color },
+}
+
+/// Who a state is shown to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Viewer {
+    Player(Color),
+    Spectator,
+    // sees everything, this is used to check moves
+    Referee,
+}
+
+impl Variant {
+    pub fn can_see(self, viewer: Viewer, piece: Piece<PlayerColor>) -> bool {
+        match self {
+            Variant::Standard => true,
+            Variant::LightAndShadow { shadow } => {
+                let is_ninja =
+                    piece.1 == PieceKind::Pawn && piece.0.is_red == (shadow == Color::Red);
+                !is_ninja || viewer == Viewer::Referee || viewer == Viewer::Player(shadow)
+            }
+        }
+    }
+
+    // the state as seen by `viewer`, hidden pieces are removed from the board
+    pub fn view(
+        self,
+        state: &State<NamedField, PlayerColor>,
+        viewer: Viewer,
+    ) -> State<NamedField, PlayerColor> {
+        let mut res = state.clone();
+        for square in &mut res.board {
+            if square.is_some_and(|p| !self.can_see(viewer, p)) {
+                *square = None;
+            }
+        }
+        res
+    }
+
+    // play `mv` for the player to move, moves into check and passes are checked on the board that they can see
+    // otherwise a rejected move would show where the hidden pieces are
+    pub fn make_move(
+        self,
+        state: State,
+        mv: NamedMove,
+        rules: &RuleSet,
+    ) -> Result<State, MoveError> {
+        let named: State<NamedField, PlayerColor> = state.clone().translate();
+        let seen: State = self.view(&named, Self::mover(&named)).translate();
+        seen.make_spirit_move(mv.card_name(), mv.from, mv.to, mv.spirit, rules)?;
+
+        let mut res = state;
+        res.apply(mv.translate(res.active_eq_red));
+        Ok(res)
+    }
+
+    // same as `State::outcome_with`, the legal moves are the ones that `make_move` allows
+    pub fn outcome(
+        self,
+        state: &State<NamedField, PlayerColor>,
+        rules: &RuleSet,
+    ) -> Option<Outcome<PlayerColor>> {
+        self.view(state, Self::mover(state)).outcome_with(rules)
+    }
+
+    fn mover(state: &State<NamedField, PlayerColor>) -> Viewer {
+        Viewer::Player([Color::Blue, Color::Red][state.active_eq_red as usize])
+    }
+
+    // the move history entry as seen by `viewer`, moves of hidden pieces only show the card
+    pub fn view_move(self, viewer: Viewer, state: &State, mv: NamedMove) -> String {
+        let from: Perspective = mv.from.translate(state.active_eq_red);
+        let visible = mv.is_pass()
+            || state.board[from.index()].is_none_or(|Piece(player, kind)| {
+                self.can_see(viewer, Piece(player.translate(state.active_eq_red), kind))
+            });
+        match visible {
+            true => mv.to_string(),
+            false => mv.card_name().to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Variant::Standard => f.write_str("standard"),
+            Variant::LightAndShadow {
+                shadow: Color::Blue,
+            } => f.write_str("light_and_shadow:blue"),
+            Variant::LightAndShadow { shadow: Color::Red } => f.write_str("light_and_shadow:red"),
+        }
+    }
+}
+
+impl FromStr for Variant {
+    type Err = Box<dyn Error + Send + Sync>;
+
+    // the shadow color is written after the variant, like `light_and_shadow:red`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Variant::Standard),
+            "light_and_shadow:blue" => Ok(Variant::LightAndShadow {
+                shadow: Color::Blue,
+            }),
+            "light_and_shadow:red" => Ok(Variant::LightAndShadow { shadow: Color::Red }),
+            _ => Err("unknown variant".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_from_str;
+
+    #[test]
+    fn hidden_check() {
+        // blue moves the king next to a red pawn, the cards are ox, boar, horse, elephant and crab
+        let board = board_from_str("0020000000003000000000400");
+        let named = State::from_board(board, [0, 1, 2, 3, 4]);
+        let state: State = named.clone().translate();
+        let mv: NamedMove = "ox:c1c2".parse().unwrap();
+
+        let res = Variant::Standard.make_move(state.clone(), mv, &RuleSet::DEFAULT);
+        assert_eq!(res.err(), Some(MoveError::SelfCheck));
+        let res = Variant::Standard.make_move(state.clone(), mv, &RuleSet::OFFICIAL);
+        assert!(res.is_ok());
+
+        // blue can not see the red pawn when it is a ninja
+        let shadow = Variant::LightAndShadow { shadow: Color::Red };
+        let next = shadow.make_move(state, mv, &RuleSet::DEFAULT).unwrap();
+        let next: State<NamedField, PlayerColor> = next.translate();
+        assert_eq!(next.board[7], named.board[2]);
+        assert_eq!(next.board[12], named.board[12]);
+        assert_eq!(shadow.outcome(&next, &RuleSet::DEFAULT), None);
+    }
+}
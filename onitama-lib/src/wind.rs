@@ -0,0 +1,184 @@
+//! Rules for the wind spirit from the Way of the Wind expansion.
+//!
+//! The spirit belongs to nobody. Cards without spirit moves can move the spirit
+//! instead of one of your own pieces. Cards with spirit moves first move one of
+//! your own pieces and then the spirit. The spirit swaps places with pawns of
+//! either player, it can never capture and it can not move onto a king.
+//! Pieces can not capture the spirit.
+//!
+//! The cards with spirit moves are not built in, they are loaded as custom cards
+//! like in [`cards::load_cards`].
+
+use crate::{
+    apply_offset, cards, is_check,
+    rules::RuleSet,
+    state::{PlayerTurn, State},
+    Move, PieceKind,
+};
+
+pub fn spirit(game: &State) -> Option<usize> {
+    game.board
+        .iter()
+        .position(|p| p.is_some_and(|x| x.1 == PieceKind::Spirit))
+}
+
+fn spirit_can_enter(game: &State, pos: usize) -> bool {
+    game.board[pos].is_none_or(|x| x.1 == PieceKind::Pawn)
+}
+
+// all moves that do not walk into check, for games with the wind spirit or its cards
+// moves into check are included when `rules` allow them
+pub fn legal_moves(game: &State, rules: &RuleSet) -> Vec<Move> {
+    if game.board_outcome(rules).is_some() {
+        return Vec::new();
+    }
+    let mut res = allowed_moves(game, rules);
+    if res.is_empty() && (rules.self_check || !is_check(game)) {
+        res = game.cards[&PlayerTurn::ACTIVE].map(Move::pass).to_vec();
+    }
+    res
+}
+
+// same as `crate::can_pass_with`, for games with the wind spirit or its cards
+pub(crate) fn can_pass(game: &State, rules: &RuleSet) -> bool {
+    allowed_moves(game, rules).is_empty() && (rules.self_check || !is_check(game))
+}
+
+// moves of the pieces and the spirit, moves into check are only included when `rules` allow them
+fn allowed_moves(game: &State, rules: &RuleSet) -> Vec<Move> {
+    let mut res = moves(game);
+    res.retain(|&m| {
+        let mut next = game.clone();
+        next.move_pieces(m);
+        rules.self_check || !is_check(&next)
+    });
+    res
+}
+
+// same as `legal_moves`, but including moves that walk into check and without passes
+// without the spirit on the board, cards with spirit moves can not be played
+pub(crate) fn moves(game: &State) -> Vec<Move> {
+    let spirit = spirit(game);
+
+    let mut res = Vec::new();
+    for &card in &game.cards[&PlayerTurn::ACTIVE] {
+        let spirit_offsets = cards::spirit_moves(card);
+        for from in 0..25 {
+            let own = game.board[from]
+                .is_some_and(|x| x.0 == PlayerTurn::ACTIVE && x.1 != PieceKind::Spirit);
+            let moves_spirit = Some(from) == spirit && spirit_offsets.is_empty();
+            if !own && !moves_spirit {
+                continue;
+            }
+
+            let targets = cards::card(card)
+                .1
+                .iter()
+                .filter_map(|&offset| apply_offset(offset, from));
+            for to in targets {
+                let m = Move {
+                    card,
+                    from,
+                    to,
+                    spirit: None,
+                };
+                if moves_spirit {
+                    if spirit_can_enter(game, to) {
+                        res.push(m);
+                    }
+                    continue;
+                }
+                if game.board[to]
+                    .is_some_and(|x| x.0 == PlayerTurn::ACTIVE || x.1 == PieceKind::Spirit)
+                {
+                    continue;
+                }
+                if spirit_offsets.is_empty() {
+                    res.push(m);
+                    continue;
+                }
+                let Some(spirit) = spirit else {
+                    continue;
+                };
+
+                let mut moved = game.clone();
+                moved.move_pieces(m);
+                let spirit_targets = spirit_offsets
+                    .iter()
+                    .filter_map(|&offset| apply_offset(offset, spirit))
+                    .filter(|&target| spirit_can_enter(&moved, target));
+                for target in spirit_targets {
+                    res.push(Move {
+                        spirit: Some(target),
+                        ..m
+                    });
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::{bitboard::BitState, legal_moves, state::Perspective, CARDS};
+
+    // two made up cards that move the spirit, with ox, boar and crab
+    fn wind_game() -> State {
+        let json = r#"[
+            {"name": "test_gust", "moves": [[-1, 1], [1, 1]], "spirit_moves": [[-1, 1], [1, -1]], "color": "red"},
+            {"name": "test_draft", "moves": [[0, 1]], "spirit_moves": [[-1, 0], [1, 0]], "color": "blue"}
+        ]"#;
+        let wind = cards::load_cards(json).unwrap();
+        let cards = [wind[0], 0, wind[1], 1, 4];
+        State::new_game(cards).translate()
+    }
+
+    #[test]
+    fn built_in_cards() {
+        for card in 0..CARDS.len() {
+            assert!(cards::spirit_moves(card).is_empty());
+        }
+        assert!(spirit(&wind_game()).is_some());
+        assert!(wind_game().outcome().is_none());
+    }
+
+    #[test]
+    fn moves_are_accepted() {
+        let game = wind_game();
+        let moves: Vec<_> = legal_moves(&game).collect();
+        assert!(!moves.is_empty());
+        for m in moves {
+            let name = cards::card(m.card).0;
+            assert_eq!(m.spirit.is_some(), name.starts_with("test_"));
+            let spirit = m.spirit.map(Perspective::from_index);
+            let next = game.clone().make_spirit_move(
+                name,
+                Perspective::from_index(m.from),
+                Perspective::from_index(m.to),
+                spirit,
+                &RuleSet::DEFAULT,
+            );
+            assert!(next.is_ok(), "{:?}", m);
+        }
+    }
+
+    #[test]
+    fn no_bit_state() {
+        let mut game = wind_game();
+        assert!(BitState::try_from(&game).is_err());
+        assert!(crate::perft::perft(&game, 1).is_err());
+
+        // the cards still move the spirit when it is not on the board
+        let pos = spirit(&game).unwrap();
+        game.board[pos] = None;
+        assert!(BitState::try_from(&game).is_err());
+        assert!(legal_moves(&game).all(|m| m.spirit.is_none()));
+
+        let game: State = State::new_game([0, 1, 2, 3, 4]).translate();
+        assert!(BitState::try_from(&game).is_ok());
+    }
+}
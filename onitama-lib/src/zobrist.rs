@@ -0,0 +1,290 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    bitboard::{BitState, ACTIVE, WAITING},
+    Move,
+};
+
+// card indices are stored as u8 in `BitState`
+const CARD_SLOTS: usize = 256;
+
+pub struct Keys {
+    // indexed by [is_red][is_king][square], squares are from blue's perspective
+    pieces: [[[u64; 25]; 2]; 2],
+    // indexed by [is_red][card]
+    hands: [[u64; CARD_SLOTS]; 2],
+    table: [u64; CARD_SLOTS],
+    red_to_move: u64,
+}
+
+pub static KEYS: Keys = Keys::new();
+
+const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+impl Keys {
+    const fn new() -> Self {
+        let mut seed = 0x6F6E6974616D61;
+        let mut res = Keys {
+            pieces: [[[0; 25]; 2]; 2],
+            hands: [[0; CARD_SLOTS]; 2],
+            table: [0; CARD_SLOTS],
+            red_to_move: 0,
+        };
+        let mut i = 0;
+        while i < 25 {
+            res.pieces[0][0][i] = splitmix64(&mut seed);
+            res.pieces[0][1][i] = splitmix64(&mut seed);
+            res.pieces[1][0][i] = splitmix64(&mut seed);
+            res.pieces[1][1][i] = splitmix64(&mut seed);
+            i += 1;
+        }
+        let mut i = 0;
+        while i < CARD_SLOTS {
+            res.hands[0][i] = splitmix64(&mut seed);
+            res.hands[1][i] = splitmix64(&mut seed);
+            res.table[i] = splitmix64(&mut seed);
+            i += 1;
+        }
+        res.red_to_move = splitmix64(&mut seed);
+        res
+    }
+}
+
+// the board is stored from the perspective of the active player, the keys use blue's perspective
+fn square(pos: usize, active_eq_red: bool) -> usize {
+    match active_eq_red {
+        true => 24 - pos,
+        false => pos,
+    }
+}
+
+impl BitState {
+    pub fn key(&self) -> u64 {
+        let mut key = 0;
+        for side in [ACTIVE, WAITING] {
+            let is_red = (side == ACTIVE) == self.active_eq_red;
+            for (is_king, mask) in [(0, self.pawns[side]), (1, self.kings[side])] {
+                for pos in (0..25).filter(|pos| mask & 1 << pos != 0) {
+                    key ^= KEYS.pieces[is_red as usize][is_king][square(pos, self.active_eq_red)];
+                }
+            }
+            for card in self.cards[side] {
+                key ^= KEYS.hands[is_red as usize][card as usize];
+            }
+        }
+        key ^= KEYS.table[self.table_card as usize];
+        if self.active_eq_red {
+            key ^= KEYS.red_to_move;
+        }
+        key
+    }
+
+    // the key after `self.play(m)`, given that `key` is the key of `self`
+    pub fn play_key(&self, mut key: u64, m: Move) -> u64 {
+        let red = self.active_eq_red as usize;
+        let (from, to) = (1 << m.from, 1 << m.to);
+        let (from_sq, to_sq) = (
+            square(m.from, self.active_eq_red),
+            square(m.to, self.active_eq_red),
+        );
+
+        // passes only change the cards
+        if !m.is_pass() {
+            let is_king = (self.kings[ACTIVE] & from != 0) as usize;
+            key ^= KEYS.pieces[red][is_king][from_sq] ^ KEYS.pieces[red][is_king][to_sq];
+            if self.pawns[WAITING] & to != 0 {
+                key ^= KEYS.pieces[red ^ 1][0][to_sq];
+            }
+            if self.kings[WAITING] & to != 0 {
+                key ^= KEYS.pieces[red ^ 1][1][to_sq];
+            }
+        }
+
+        let table = self.table_card as usize;
+        key ^= KEYS.hands[red][m.card] ^ KEYS.hands[red][table];
+        key ^= KEYS.table[table] ^ KEYS.table[m.card];
+        key ^ KEYS.red_to_move
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bound {
+    Exact,
+    // the score is at least this high
+    Lower,
+    // the score is at most this high
+    Upper,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Entry {
+    pub depth: u8,
+    pub score: i32,
+    pub bound: Bound,
+    pub best: Option<Move>,
+}
+
+impl Entry {
+    // layout: score (32 bits), depth (8), bound (2), has best (1), card (8), from (5), to (5)
+    fn pack(self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        let best = match self.best {
+            Some(m) => 1 << 18 | (m.card as u64) << 10 | (m.from as u64) << 5 | m.to as u64,
+            None => 0,
+        };
+        (self.score as u32 as u64) << 32 | (self.depth as u64) << 24 | bound << 22 | best
+    }
+
+    fn unpack(data: u64) -> Self {
+        Entry {
+            depth: (data >> 24) as u8,
+            score: (data >> 32) as u32 as i32,
+            bound: [Bound::Exact, Bound::Lower, Bound::Upper][(data >> 22 & 3) as usize],
+            best: (data >> 18 & 1 != 0).then_some(Move {
+                card: (data >> 10 & 0xFF) as usize,
+                from: (data >> 5 & 0x1F) as usize,
+                to: (data & 0x1F) as usize,
+                spirit: None,
+            }),
+        }
+    }
+}
+
+// fixed size hash table with one entry per slot, threads can share it without locking
+pub struct TranspositionTable {
+    // (key ^ packed entry, packed entry), an empty slot is all zeros
+    // a slot that two threads wrote at the same time has the wrong key, so it is never used
+    slots: Vec<(AtomicU64, AtomicU64)>,
+}
+
+impl TranspositionTable {
+    // the number of slots is rounded down to a power of two
+    pub fn new(size_mb: usize) -> Self {
+        let slots = (size_mb << 20) / std::mem::size_of::<(u64, u64)>();
+        let slots = 1 << (usize::BITS - 1 - slots.max(1).leading_zeros());
+        TranspositionTable {
+            slots: (0..slots)
+                .map(|_| (AtomicU64::new(0), AtomicU64::new(0)))
+                .collect(),
+        }
+    }
+
+    fn index(&self, key: u64) -> usize {
+        key as usize & (self.slots.len() - 1)
+    }
+
+    fn load(&self, key: u64) -> Option<u64> {
+        let (check, data) = &self.slots[self.index(key)];
+        let data = data.load(Ordering::Relaxed);
+        (check.load(Ordering::Relaxed) ^ data == key && key != 0).then_some(data)
+    }
+
+    pub fn get(&self, key: u64) -> Option<Entry> {
+        self.load(key).map(Entry::unpack)
+    }
+
+    // entries for the same position are only replaced by deeper searches
+    pub fn insert(&self, key: u64, entry: Entry) {
+        if self
+            .load(key)
+            .is_some_and(|data| Entry::unpack(data).depth > entry.depth)
+        {
+            return;
+        }
+        let (check, data) = &self.slots[self.index(key)];
+        let packed = entry.pack();
+        check.store(key ^ packed, Ordering::Relaxed);
+        data.store(packed, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for (check, data) in &self.slots {
+            check.store(0, Ordering::Relaxed);
+            data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+    use std::convert::TryFrom;
+
+    fn start() -> BitState {
+        BitState::try_from(&State::new_game([0, 1, 2, 3, 4])).unwrap()
+    }
+
+    #[test]
+    fn pack_unpack() {
+        let moves = [
+            None,
+            Some(Move::pass(255)),
+            Some(Move {
+                card: 17,
+                from: 24,
+                to: 3,
+                spirit: None,
+            }),
+        ];
+        for best in moves {
+            for (score, depth) in [(0, 0), (-999_990, 64), (123, 255)] {
+                for bound in [Bound::Exact, Bound::Lower, Bound::Upper] {
+                    let entry = Entry {
+                        depth,
+                        score,
+                        bound,
+                        best,
+                    };
+                    assert_eq!(Entry::unpack(entry.pack()), entry);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn play_key() {
+        let mut states = vec![start()];
+        for _ in 0..3 {
+            states = states
+                .iter()
+                .flat_map(|s| s.moves().map(move |m| (*s, m)))
+                .map(|(s, m)| {
+                    assert_eq!(s.play_key(s.key(), m), s.play(m).key());
+                    s.play(m)
+                })
+                .collect();
+        }
+    }
+
+    #[test]
+    fn insert_get() {
+        let tt = TranspositionTable::new(1);
+        let key = start().key();
+        let entry = |depth| Entry {
+            depth,
+            score: -5,
+            bound: Bound::Upper,
+            best: None,
+        };
+        assert_eq!(tt.get(key), None);
+        tt.insert(key, entry(3));
+        assert_eq!(tt.get(key), Some(entry(3)));
+        // shallower searches do not replace deeper ones
+        tt.insert(key, entry(1));
+        assert_eq!(tt.get(key), Some(entry(3)));
+        assert_eq!(tt.get(key ^ 1 << 40), None);
+        tt.clear();
+        assert_eq!(tt.get(key), None);
+    }
+}
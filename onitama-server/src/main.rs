@@ -1,20 +1,27 @@
-use onitama_lib::state::{NamedField, Piece, PlayerColor, PlayerTurn, State};
+use onitama_lib::book::Book;
+use onitama_lib::draw::DrawTracker;
+use onitama_lib::notation::{MoveList, NamedMove};
+use onitama_lib::record::{GameRecord, RecordMove};
+use onitama_lib::rules::RuleSet;
+use onitama_lib::state::{NamedField, PlayerColor, State, Translate};
+use onitama_lib::variant::{Variant, Viewer};
 use onitama_lib::{
-    board_from_str, card_to_pos, Color, ExtraState, LitamaMsg, PieceKind, Sides, StateMsg, CARDS,
-    DEFAULT_BOARD,
+    board_to_str, cards, legal_moves_with, CardPool, Color, ExtraState, LitamaMsg, MoveError,
+    Sides, StateMsg, CARDS,
 };
 use rand::random;
 use rand::seq::SliceRandom;
 use rust_query::migration::{schema, Config};
 use rust_query::{Database, TableRow, Transaction};
+use std::convert::TryInto;
 use std::error::Error;
-use std::iter::FromIterator;
-use std::str::FromStr;
+use std::sync::OnceLock;
 
 use simple_websockets::{Event, Message, Responder};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 #[schema(Schema)]
+#[version(0..=1)]
 pub mod vN {
     pub struct Match {
         #[unique]
@@ -30,16 +37,28 @@ pub mod vN {
 
         // either "red" or "blue"
         pub create_color: String,
-        // concatenation of blue1,blue2,red1,red2,side
+        // before version 1 the variant and rules were added after the cards
+        #[version(..1)]
         pub starting_cards: String,
+        // concatenation of blue1,blue2,red1,red2,side
+        #[version(1..)]
+        pub cards: String,
+        // like "standard" or "light_and_shadow:red"
+        #[version(1..)]
+        pub variant: String,
+        // like "self_check=no;temple=c;first=side;ply_limit=none"
+        #[version(1..)]
+        pub rules: String,
     }
 }
-use v0::*;
+use v1::*;
+
+static BOOK: OnceLock<Book> = OnceLock::new();
 
 pub struct Client {
     responder: Responder,
-    // these are game_ids
-    subscriptions: HashSet<String>,
+    // these are game_ids, together with who is watching
+    subscriptions: HashMap<String, Viewer>,
 }
 
 impl Client {
@@ -51,16 +70,51 @@ impl Client {
 }
 
 fn main() {
-    // listen for WebSockets on port 8080:
-    let event_hub = simple_websockets::launch(5000).expect("failed to listen on port 5000");
-    // map between client ids and the client's `Responder`:
-    let mut clients: HashMap<u64, Client> = HashMap::new();
-
     let db: Database<Schema> = Database::migrator(Config::open("db.sqlite"))
         .unwrap()
+        .migrate(|txn| v0::migrate::Schema {
+            r#match: txn.migrate_ok(|old: v0::Match!(starting_cards)| {
+                let (cards, variant, rules) = split_starting_cards(&old.starting_cards);
+                v0::migrate::Match {
+                    cards,
+                    variant,
+                    rules,
+                }
+            }),
+        })
         .finish()
         .unwrap();
 
+    // house rule cards and the Way of the Wind cards, these can be used with the "custom" card pool
+    if let Ok(json) = std::fs::read_to_string("cards.json") {
+        let cards = cards::load_cards(&json).expect("failed to load cards.json");
+        println!("loaded {} custom cards", cards.len());
+    }
+
+    // opening book for the move hints, see the `book` binary of onitama-lib
+    if let Ok(file) = std::fs::File::open("book.bin") {
+        let book = Book::read(&mut std::io::BufReader::new(file)).expect("failed to load book.bin");
+        println!("loaded {} book positions", book.len());
+        BOOK.set(book).ok();
+    }
+
+    // `onitama-server export <match_id>` prints the game record instead of starting the server
+    let args: Vec<_> = std::env::args().collect();
+    if let [_, cmd, match_id] = &args[..] {
+        if cmd == "export" {
+            match db.transaction_mut(|txn| read_record(txn, match_id)) {
+                Ok(record) => print!("{record}"),
+                Err(err) => eprintln!("{err}"),
+            }
+            return;
+        }
+    }
+
+    // listen for WebSockets on port 8080:
+    let event_hub = simple_websockets::launch(5000).expect("failed to listen on port 5000");
+    // map between client ids and the client's `Responder`:
+    let mut clients: HashMap<u64, Client> = HashMap::new();
+
     loop {
         match event_hub.poll_event() {
             Event::Connect(client_id, responder) => {
@@ -70,7 +124,7 @@ fn main() {
                     client_id,
                     Client {
                         responder,
-                        subscriptions: HashSet::new(),
+                        subscriptions: HashMap::new(),
                     },
                 );
             }
@@ -95,7 +149,8 @@ fn main() {
                         Message::Binary(_) => "<binary data>".to_owned(),
                     };
                     let error = err.to_string();
-                    client.send_msg(LitamaMsg::Error { error, query });
+                    let code = err.downcast_ref::<MoveError>().copied();
+                    client.send_msg(LitamaMsg::Error { error, query, code });
                     client.responder.close();
                     clients.remove(&client_id);
                 }
@@ -124,10 +179,33 @@ pub fn handle_message(
         let match_id = &random::<[u8; 12]>().map(|x| format!("{x:x}")).join("");
         let blue_token = &random::<[u8; 32]>().map(|x| format!("{x:x}")).join("");
         let red_token = &random::<[u8; 32]>().map(|x| format!("{x:x}")).join("");
+        // optional comma separated list of card pools, like "base,senseis_path"
+        let pools = match parts.get(2) {
+            Some(pools) => pools
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<Vec<CardPool>, _>>()?,
+            None => vec![CardPool::Base],
+        };
+        let variant: Variant = match parts.get(3) {
+            Some(variant) => variant.parse()?,
+            None => Variant::Standard,
+        };
+        let rules: RuleSet = match parts.get(4) {
+            Some(rules) => rules.parse()?,
+            None => RuleSet::DEFAULT,
+        };
+        let mut pool: Vec<_> = pools.into_iter().flat_map(CardPool::cards).collect();
+        pool.sort();
+        pool.dedup();
+        if pool.len() < 5 {
+            return Err("the card pools have fewer than 5 cards".into());
+        }
+
         let mut rng = rand::thread_rng();
-        let cards: Vec<_> = onitama_lib::CARDS
+        let cards: Vec<_> = pool
             .choose_multiple(&mut rng, 5)
-            .map(|x| x.0)
+            .map(|&x| cards::card(x).0)
             .collect();
 
         txn.insert(Match {
@@ -142,7 +220,9 @@ pub fn handle_message(
                 .copied()
                 .unwrap()
                 .to_owned(),
-            starting_cards: cards.join(","),
+            cards: cards.join(","),
+            variant: variant.to_string(),
+            rules: rules.to_string(),
         })
         .unwrap();
 
@@ -180,29 +260,26 @@ pub fn handle_message(
             });
 
             for other in clients.values() {
-                if other.subscriptions.contains(match_id) {
+                if let Some(&viewer) = other.subscriptions.get(match_id) {
                     other.send_msg(LitamaMsg::State {
                         match_id: match_id.to_owned(),
-                        state: read_state_msg(&txn, m_row), // TODO: maybe optimize this?
+                        state: read_state_msg(&txn, m_row, viewer)?, // TODO: maybe optimize this?
                     });
                 }
             }
         }
         "state" => {
+            let viewer = read_viewer(&txn, m_row, parts.get(2).copied())?;
             client.send_msg(LitamaMsg::State {
                 match_id: match_id.to_owned(),
-                state: read_state_msg(&txn, m_row),
+                state: read_state_msg(&txn, m_row, viewer)?,
             });
         }
         "move" => {
             let token = *parts.get(2).ok_or("expected token")?;
             let card = *parts.get(3).ok_or("expected card")?;
             let from_to = *parts.get(4).ok_or("expected move")?;
-            if from_to.len() != 4 || !from_to.is_ascii() {
-                return Err("move has unexpected len or is not ascii".into());
-            }
-            let from = NamedField::from_str(&from_to[..2])?;
-            let to = NamedField::from_str(&from_to[2..])?;
+            let mv = NamedMove::from_parts(card, from_to)?;
 
             let is_red = if token == m.create_token {
                 m.create_color == "red"
@@ -212,25 +289,26 @@ pub fn handle_message(
                 return Err("token not recognized".into());
             };
 
-            let msg = read_state_msg(&txn, m_row);
+            let msg = read_state_msg(&txn, m_row, Viewer::Referee)?;
             let StateMsg::InProgress { extra, .. } = msg else {
-                return Err("game must be in progress".into());
+                return Err(MoveError::NotInProgress.into());
             };
 
-            let state = State::from_state(extra);
+            let rules = extra.rules;
+            let variant: Variant = m.variant.parse()?;
+            let state = State::from_state(extra)?;
             if state.active_eq_red != is_red {
-                return Err("it is not your turn".into());
+                return Err(MoveError::NotYourTurn.into());
             }
 
-            let state: State = state.translate();
-            state.make_move(card, from, to)?;
+            variant.make_move(state.translate(), mv, &rules)?;
 
             {
                 let history = &mut txn.mutable(m_row).history;
                 if !history.is_empty() {
                     history.push(',');
                 }
-                history.push_str(&format!("{card}:{from_to}"));
+                history.push_str(&mv.to_string());
             }
 
             client.send_msg(LitamaMsg::Move {
@@ -238,16 +316,18 @@ pub fn handle_message(
             });
 
             for other in clients.values() {
-                if other.subscriptions.contains(match_id) {
+                if let Some(&viewer) = other.subscriptions.get(match_id) {
                     other.send_msg(LitamaMsg::State {
                         match_id: match_id.to_owned(),
-                        state: read_state_msg(&txn, m_row), // TODO: maybe optimize this?
+                        state: read_state_msg(&txn, m_row, viewer)?, // TODO: maybe optimize this?
                     });
                 }
             }
         }
         "spectate" => {
-            client.subscriptions.insert(match_id.to_owned());
+            // players can add their token to see their own hidden pieces
+            let viewer = read_viewer(&txn, m_row, parts.get(2).copied())?;
+            client.subscriptions.insert(match_id.to_owned(), viewer);
 
             client.send_msg(LitamaMsg::Spectate {
                 match_id: match_id.to_owned(),
@@ -255,7 +335,7 @@ pub fn handle_message(
 
             client.send_msg(LitamaMsg::State {
                 match_id: match_id.to_owned(),
-                state: read_state_msg(&txn, m_row),
+                state: read_state_msg(&txn, m_row, viewer)?,
             });
         }
         _ => return Err("unknown command".into()),
@@ -264,15 +344,19 @@ pub fn handle_message(
     Ok(())
 }
 
-pub fn read_state_msg<'a>(txn: &Transaction<Schema>, m_row: TableRow<Match>) -> StateMsg {
+pub fn read_state_msg<'a>(
+    txn: &Transaction<Schema>,
+    m_row: TableRow<Match>,
+    viewer: Viewer,
+) -> Result<StateMsg, Box<dyn Error + Send + Sync>> {
     let m = txn.lazy(m_row);
     let Some(join_name) = &m.join_name else {
-        return StateMsg::Waiting {
+        return Ok(StateMsg::Waiting {
             usernames: Sides {
                 blue: m.create_name.clone(),
                 red: m.create_name.clone(),
             },
-        };
+        });
     };
 
     let (blue_name, red_name) = if m.create_color == "blue" {
@@ -281,36 +365,25 @@ pub fn read_state_msg<'a>(txn: &Transaction<Schema>, m_row: TableRow<Match>) ->
         (join_name, &m.create_name)
     };
 
-    let moves: Vec<_> = match m.history.is_empty() {
-        true => Vec::new(),
-        false => m.history.split(',').map(ToOwned::to_owned).collect(),
-    };
+    let moves: MoveList = m.history.parse()?;
+    let (starting_cards, variant, rules) = read_setup(&m.cards, &m.variant, &m.rules)?;
 
-    let starting_cards: Vec<_> = m
-        .starting_cards
-        .split(',')
-        .map(|x| card_to_pos(x.to_owned()))
+    let state = State::new_game_with(starting_cards, &rules);
+    // clients only know the built in cards
+    let custom_cards = starting_cards
+        .iter()
+        .filter(|&&c| c >= CARDS.len())
+        .map(|&c| cards::card_def(c))
         .collect();
-
-    let state = State {
-        board: board_from_str(DEFAULT_BOARD),
-        table_card: starting_cards[4],
-        cards: HashMap::from_iter([
-            (PlayerColor::BLUE, [starting_cards[0], starting_cards[1]]),
-            (PlayerColor::RED, [starting_cards[2], starting_cards[3]]),
-        ]),
-        // side card determines starting player
-        active_eq_red: CARDS[starting_cards[4]].2 == PlayerColor::RED,
-        _p: std::marker::PhantomData::<NamedField>,
-    };
     let starting_cards = state.cards();
 
+    let mut history = vec![];
+    let mut draws = DrawTracker::new(&state, &rules);
     let mut state: State = state.translate();
-    for m in &moves {
-        let (card, from_to) = m.split_once(':').unwrap();
-        let from = NamedField::from_str(&from_to[..2]).unwrap();
-        let to = NamedField::from_str(&from_to[2..]).unwrap();
-        state = state.make_move(card, from, to).unwrap();
+    for m in &moves.0 {
+        history.push(variant.view_move(viewer, &state, *m));
+        state = variant.make_move(state, *m, &rules)?;
+        draws.push(&state.clone().translate());
     }
 
     let usernames = Sides {
@@ -318,18 +391,30 @@ pub fn read_state_msg<'a>(txn: &Transaction<Schema>, m_row: TableRow<Match>) ->
         red: red_name.clone(),
     };
 
-    // check if the current player has lost
-    let lost = !state
-        .board
-        .iter()
-        .any(|x| *x == Some(Piece(PlayerTurn::ACTIVE, PieceKind::King)))
-        || state.board[22] == Some(Piece(PlayerTurn::WAITING, PieceKind::King));
-    let winner = lost.then(|| match state.active_eq_red {
-        true => "blue",
-        false => "red",
-    });
+    // hints would show where hidden pieces are
+    let book_moves = match (BOOK.get(), variant) {
+        (Some(book), Variant::Standard) if Book::applies_to(&rules) && draws.draw().is_none() => {
+            let legal: Vec<_> = legal_moves_with(&state, &rules).collect();
+            book.probe_state(&state)
+                .iter()
+                .filter(|m| legal.contains(&m.mv))
+                .map(|m| {
+                    let mv: NamedMove = m.mv.translate(state.active_eq_red);
+                    mv.to_string()
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    };
 
     let state: State<NamedField, PlayerColor> = state.translate();
+    // clients take the winner from here, so the game ends the same way for everyone
+    let winner = match variant.outcome(&state, &rules) {
+        Some(o) if o.winner.is_red => Some("red"),
+        Some(_) => Some("blue"),
+        // repeating positions and long games end without a winner
+        None => draws.draw().map(|_| "draw"),
+    };
 
     let extra = ExtraState {
         indices: Sides {
@@ -339,24 +424,109 @@ pub fn read_state_msg<'a>(txn: &Transaction<Schema>, m_row: TableRow<Match>) ->
         current_turn: [Color::Blue, Color::Red][state.active_eq_red as usize],
         cards: state.cards(),
         starting_cards,
-        moves,
-        board: state
-            .board
-            .iter()
-            .map(|p| match p {
-                None => '0',
-                Some(Piece(PlayerColor::BLUE, PieceKind::Pawn)) => '1',
-                Some(Piece(PlayerColor::BLUE, PieceKind::King)) => '2',
-                Some(Piece(PlayerColor::RED, PieceKind::Pawn)) => '3',
-                Some(Piece(PlayerColor::RED, PieceKind::King)) => '4',
-            })
-            .collect(),
+        moves: history,
+        board: board_to_str(&variant.view(&state, viewer).board),
         winner: winner.unwrap_or("none").to_owned(),
+        rules,
+        book_moves,
+        custom_cards,
     };
 
-    if winner.is_some() {
+    Ok(if winner.is_some() {
         StateMsg::Ended { usernames, extra }
     } else {
         StateMsg::InProgress { usernames, extra }
+    })
+}
+
+pub fn read_record(
+    txn: &Transaction<Schema>,
+    match_id: &str,
+) -> Result<GameRecord, Box<dyn Error + Send + Sync>> {
+    let m = txn
+        .lazy(Match.match_id(match_id))
+        .ok_or("match does not exist")?;
+    let join_name = m.join_name.clone().ok_or("match has not been joined")?;
+
+    let (blue, red) = if m.create_color == "blue" {
+        (m.create_name.clone(), join_name)
+    } else {
+        (join_name, m.create_name.clone())
+    };
+
+    let (starting_cards, variant, rules) = read_setup(&m.cards, &m.variant, &m.rules)?;
+    let MoveList(moves) = m.history.parse()?;
+
+    // timestamps are not stored in the database
+    let mut record = GameRecord {
+        names: Sides { blue, red },
+        cards: starting_cards,
+        board: None,
+        started: None,
+        variant,
+        rules,
+        moves: moves
+            .into_iter()
+            .map(|mv| RecordMove { mv, time: None })
+            .collect(),
+        result: None,
+    };
+    // repeated positions and the ply limit end the game like in `read_state_msg`
+    record.result = record.final_result()?;
+    // the record contains every move, so it would show where the hidden pieces are
+    if variant != Variant::Standard && record.result.is_none() {
+        return Err("this variant can only be exported when the game is over".into());
+    }
+    Ok(record)
+}
+
+// matches before version 1 stored the variant and rules after the cards,
+// like "ox,boar,horse,elephant,crab,light_and_shadow:red,rules:temple=none"
+// returns the cards, the variant and the rules as they are stored now
+fn split_starting_cards(starting_cards: &str) -> (String, String, String) {
+    let parts: Vec<_> = starting_cards.split(',').collect();
+    let (cards, extra) = parts.split_at(parts.len().min(5));
+    let mut variant = Variant::Standard.to_string();
+    let mut rules = RuleSet::DEFAULT.to_string();
+    for part in extra {
+        match part.strip_prefix("rules:") {
+            Some(x) => rules = x.to_owned(),
+            None => variant = (*part).to_owned(),
+        }
     }
+    (cards.join(","), variant, rules)
+}
+
+// the five cards, the variant and the rules from the columns of a match
+fn read_setup(
+    cards: &str,
+    variant: &str,
+    rules: &str,
+) -> Result<([usize; 5], Variant, RuleSet), Box<dyn Error + Send + Sync>> {
+    let cards = cards
+        .split(',')
+        .map(|x| cards::find_card(x).ok_or("unknown card name"))
+        .collect::<Result<Vec<_>, _>>()?;
+    let cards: [usize; 5] = cards.try_into().map_err(|_| "expected 5 starting cards")?;
+    Ok((cards, variant.parse()?, rules.parse()?))
+}
+
+// players are recognized by their token, everyone else is a spectator
+fn read_viewer(
+    txn: &Transaction<Schema>,
+    m_row: TableRow<Match>,
+    token: Option<&str>,
+) -> Result<Viewer, Box<dyn Error + Send + Sync>> {
+    let m = txn.lazy(m_row);
+    let Some(token) = token else {
+        return Ok(Viewer::Spectator);
+    };
+    let is_red = if token == m.create_token {
+        m.create_color == "red"
+    } else if token == m.join_token {
+        m.create_color != "red"
+    } else {
+        return Err("token not recognized".into());
+    };
+    Ok(Viewer::Player([Color::Blue, Color::Red][is_red as usize]))
 }